@@ -1,7 +1,21 @@
-use std::fs::File;
-use std::io::prelude::*;
-use std::io::Result as IoResult;
-use crate::state::{Chip8State, QuirksConfig};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+use std::path::{Path, PathBuf};
+use crate::state::{Chip8State, QuirksConfig, DEFAULT_INSTRUCTIONS_PER_FRAME};
+use crate::screen::{parse_palette, Palette, DEFAULT_PALETTE};
+use crate::font::{Font, DEFAULT_FONT_ADDR};
+use crate::error::ErrorPolicy;
+use crate::rng::{Rng, RngMode};
+use crate::config::RomConfig;
+use crate::database::{rom_hash, Database};
+use crate::savestate;
+use crate::breakpoint;
+use crate::rewind::DEFAULT_REWIND_SECONDS;
+use crate::undo::{UndoLog, DEFAULT_UNDO_LOG_SIZE};
+use crate::cartridge::{self, Cartridge};
+use clap::ValueEnum;
+use serde::Deserialize;
 
 pub struct Args {
     pub file: String,
@@ -10,18 +24,371 @@ pub struct Args {
     pub pixel_size: i32,
     pub stop: bool,
     pub debug_mode: bool,
-    pub quirks_config: QuirksConfig
+    pub quirks_config: QuirksConfig,
+    pub palette: Palette,
+    pub font: Font,
+    pub font_addr: u16,
+    // Directory for the RPL user flags files. None means next to the
+    // ROM
+    pub rpl_dir: Option<String>,
+    pub error_policy: ErrorPolicy,
+    pub seed: u64,
+    pub rng_mode: RngMode,
+    pub instructions_per_frame: u32,
+    // Keyboard keys for the CHIP-8 keys that don't use the default one
+    pub key_map: BTreeMap<u8, String>,
+    // The ROM database entry the settings were taken from
+    pub database_entry: Option<String>,
+    // Save state to load right after starting
+    pub load_state: Option<String>,
+    pub rewind_seconds: u32,
+    // Movie files to record to and to play back
+    pub record: Option<String>,
+    pub play: Option<String>,
+    // Names for addresses from the ROM config, usable in breakpoints
+    pub labels: BTreeMap<String, u16>,
+    // The binary image of the ROM, compiled if it was a cartridge, and
+    // its SHA-1 which save states and movies are tied to
+    pub rom: Vec<u8>,
+    pub rom_hash: String,
+}
+
+/// Quirk presets selectable from the command line
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum Chip8Quirks {
+    Chip8,
+    SuperChip,
+    XoChip
+}
+
+impl Chip8Quirks {
+    pub fn quirks_config(&self) -> QuirksConfig {
+        match self {
+            Self::Chip8 => QuirksConfig::get_chip8(),
+            Self::SuperChip => QuirksConfig::get_super_chip(),
+            Self::XoChip => QuirksConfig::get_xo_chip(),
+        }
+    }
+}
+
+/// Parse an address given either in decimal or in hex with the 0x
+/// prefix
+pub fn parse_addr(addr: &str) -> Result<u16, String> {
+    let parsed = match addr.strip_prefix("0x").or_else(|| addr.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => addr.parse::<u16>(),
+    };
+    parsed.map_err(|e| format!("{} is not a valid address: {}", addr, e))
+}
+
+/// Parse a single quirk override given as NAME=on or NAME=off
+pub fn parse_quirk(quirk: &str) -> Result<(String, bool), String> {
+    let (name, value) = quirk.split_once('=')
+        .ok_or_else(|| format!("{} is not NAME=on or NAME=off", quirk))?;
+    let value = match value {
+        "on" | "true" | "1" => true,
+        "off" | "false" | "0" => false,
+        _ => return Err(format!("{} is neither on nor off", value)),
+    };
+    // Check the name right away, so typos are reported by clap
+    QuirksConfig::get_chip8().set_quirk(name, value)?;
+    Ok((name.to_owned(), value))
+}
+
+/// Command line options shared by the binaries that run ROMs
+#[derive(clap::Args, Debug)]
+pub struct Cli {
+    /// ROM to run, either a binary image or an Octo cartridge GIF
+    #[arg(short, long)]
+    file: String,
+
+    /// Offset where to load the binary image in the CHIP-8 address
+    /// space, e.g. 0x600 for ETI-660 ROMs. Defaults to 0x200
+    #[arg(short, long, value_parser = parse_addr)]
+    offset: Option<u16>,
+
+    /// Start address of the execution. Defaults to the offset
+    #[arg(long, value_parser = parse_addr)]
+    start: Option<u16>,
+
+    /// Pixel size
+    #[arg(short, long, default_value_t = 16)]
+    pixel_size: i32,
+
+    /// Start with stopped execution
+    #[arg(short, long, default_value_t = false)]
+    stop: bool,
+
+    /// Debug mode. Draw special debug windows
+    #[arg(short, long, default_value_t = false)]
+    debug_mode: bool,
+
+    /// Pick quirks. Defaults to chip8
+    #[arg(value_enum, short, long)]
+    quirks: Option<Chip8Quirks>,
+
+    /// Turn a single quirk of the preset on or off, e.g.
+    /// shifting=on. Can be repeated
+    #[arg(long = "quirk", value_parser = parse_quirk)]
+    quirk_overrides: Vec<(String, bool)>,
+
+    /// ROM config file. By default <ROM>.toml or <ROM>.json next to
+    /// the ROM is used, if there is one
+    #[arg(long)]
+    config: Option<String>,
+
+    /// Directory with the programs.json and platforms.json files of
+    /// the CHIP-8 database. Known ROMs get their platform, quirks,
+    /// speed, colors and keys from it
+    #[arg(long)]
+    database: Option<String>,
+
+    /// Octo compiler for the source code in cartridges. Defaults to the
+    /// OCTO_CLI environment variable, or to octo-cli
+    #[arg(long)]
+    octo_cli: Option<String>,
+
+    /// Four comma separated RRGGBB colors: background, first plane,
+    /// second plane and both planes
+    #[arg(long, value_parser = parse_palette)]
+    palette: Option<Palette>,
+
+    /// Font used by FX29
+    #[arg(value_enum, long, default_value_t = Font::Vip)]
+    font: Font,
+
+    /// Address where the font is loaded in the CHIP-8 address space
+    #[arg(long, default_value_t = DEFAULT_FONT_ADDR, value_parser = parse_addr)]
+    font_addr: u16,
+
+    /// Directory where the SUPER-CHIP RPL user flags are saved.
+    /// By default they are saved next to the ROM
+    #[arg(long)]
+    rpl_dir: Option<String>,
+
+    /// Instructions executed per 60Hz frame. Defaults to 16
+    #[arg(long)]
+    instructions_per_frame: Option<u32>,
+
+    /// Save state to start from
+    #[arg(long)]
+    load_state: Option<String>,
+
+    /// How far back the rewind key can go, in seconds of emulated
+    /// time. 0 turns the rewinding off
+    #[arg(long, default_value_t = DEFAULT_REWIND_SECONDS)]
+    rewind_seconds: u32,
+
+    /// Record the keys pressed in every frame to a movie file
+    #[arg(long, conflicts_with = "play")]
+    record: Option<String>,
+
+    /// Play a movie file back instead of reading the keyboard
+    #[arg(long)]
+    play: Option<String>,
+
+    /// What to do when an instruction can't be executed
+    #[arg(value_enum, long, default_value_t = ErrorPolicy::Halt)]
+    on_error: ErrorPolicy,
+
+    /// Seed of the random number generator used by CXNN. A random one
+    /// is picked by default
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Random number generator used by CXNN
+    #[arg(value_enum, long, default_value_t = RngMode::Xorshift)]
+    rng: RngMode,
+}
+
+impl Cli {
+    /// Merge the command line with the ROM config file, the options of
+    /// an Octo cartridge and the ROM database. Options given on the
+    /// command line win over the config file, which wins over the
+    /// cartridge, then the database and then the defaults. Quirks
+    /// start from the preset, then the quirks of the cartridge or the
+    /// database are applied if the preset came from them, then the
+    /// config file's quirks and then the --quirk options.
+    pub fn to_args(&self) -> IoResult<Args> {
+        let config = match &self.config {
+            Some(path) => RomConfig::load(Path::new(path))?,
+            None => RomConfig::find_for_rom(&self.file)?.unwrap_or_default(),
+        };
+        let file = fs::read(&self.file)?;
+        let cartridge = match cartridge::is_cartridge(&file) {
+            true => Some(Cartridge::decode(&file)?),
+            false => None,
+        };
+        // Cartridges are compiled once, everything later uses the result
+        let rom = match &cartridge {
+            Some(cartridge) => {
+                let octo_cli = self.octo_cli.clone().unwrap_or_else(cartridge::default_compiler);
+                cartridge.compile_with(&octo_cli)?
+            },
+            None => file,
+        };
+        let database_match = match &self.database {
+            Some(dir) => Database::load(Path::new(dir))?.lookup(&rom),
+            None => None,
+        };
+        let options = cartridge.as_ref().map(|cartridge| &cartridge.options);
+
+        let (preset, base_quirks) = match self.quirks.or(config.quirks) {
+            Some(preset) => (preset, Vec::new()),
+            None => options
+                .and_then(|options| Some((options.preset()?, options.quirks())))
+                .or_else(|| database_match.as_ref()
+                    .map(|database_match| (database_match.preset, database_match.quirks.clone())))
+                .unwrap_or((Chip8Quirks::Chip8, Vec::new())),
+        };
+        let mut quirks_config = preset.quirks_config();
+        let overrides = base_quirks.iter().map(|(name, value)| (name, *value))
+            .chain(config.quirk.iter().map(|(name, value)| (name, *value)))
+            .chain(self.quirk_overrides.iter().map(|(name, value)| (name, *value)));
+        for (name, value) in overrides {
+            quirks_config.set_quirk(name, value)
+                .map_err(|e| IoError::new(ErrorKind::InvalidInput, e))?;
+        }
+
+        let mut key_map = database_match.as_ref()
+            .map(|database_match| database_match.key_map.clone())
+            .unwrap_or_default();
+        key_map.extend(config.key_map()?);
+
+        let offset = self.offset.or(config.offset()?)
+            .or(database_match.as_ref().and_then(|database_match| database_match.offset))
+            .unwrap_or(0x200);
+        Ok(Args {
+            file: self.file.clone(),
+            offset,
+            start: self.start.unwrap_or(offset),
+            pixel_size: self.pixel_size,
+            stop: self.stop,
+            debug_mode: self.debug_mode,
+            palette: self.palette.or(config.palette()?)
+                .or(options.and_then(|options| options.palette()))
+                .or(database_match.as_ref().and_then(|database_match| database_match.palette))
+                .unwrap_or(DEFAULT_PALETTE),
+            font: self.font,
+            font_addr: self.font_addr,
+            rpl_dir: self.rpl_dir.clone(),
+            error_policy: self.on_error,
+            seed: self.seed.unwrap_or_else(rand::random),
+            rng_mode: self.rng,
+            instructions_per_frame: self.instructions_per_frame
+                .or(config.instructions_per_frame)
+                .or(options.and_then(|options| options.tickrate))
+                .or(database_match.as_ref().and_then(|database_match| database_match.instructions_per_frame))
+                .unwrap_or(DEFAULT_INSTRUCTIONS_PER_FRAME),
+            key_map,
+            database_entry: database_match.as_ref().map(|database_match| database_match.describe()),
+            load_state: self.load_state.clone(),
+            rewind_seconds: self.rewind_seconds,
+            record: self.record.clone(),
+            play: self.play.clone(),
+            labels: config.labels()?,
+            rom_hash: rom_hash(&rom),
+            rom,
+            quirks_config,
+        })
+    }
 }
 
 impl Args {
     pub fn create_chip8(&self) -> IoResult<Chip8State> {
-        let mut file = File::open(&self.file)?;
-        let mut memory = vec![0u8; 0x200];
-        let mut contents = Vec::<u8>::new();
-        file.read_to_end(&mut contents)?;
-        memory.append(&mut contents);
-        let mut chip8_state = Chip8State::from_memory(self.quirks_config.clone(), memory);
+        let contents = self.rom.clone();
+
+        self.quirks_config.platform.check_rom_size(contents.len(), self.offset)?;
+
+        let mut chip8_state = Chip8State::new(self.quirks_config.clone());
+        // The font goes first, so a ROM loaded over it takes precedence
+        chip8_state.load_memory(self.font.memory_image(), self.font_addr as usize)
+            .map_err(|_| IoError::new(ErrorKind::InvalidInput, format!(
+                "font doesn't fit in memory at {:#x}", self.font_addr
+            )))?;
+        chip8_state.load_memory(contents, self.offset as usize)
+            .map_err(|_| IoError::new(ErrorKind::InvalidData, "ROM doesn't fit in memory"))?;
+        chip8_state.font_addr = self.font_addr;
+        chip8_state.big_font_addr = self.font_addr + self.font.small_glyphs().len() as u16;
         chip8_state.pc = self.start;
+        chip8_state.error_policy = self.error_policy;
+        chip8_state.rng = Rng::new(self.seed, self.rng_mode);
+        chip8_state.instructions_per_frame = self.instructions_per_frame;
+        // Stepping back and the breakpoints are only offered by the
+        // debugger
+        if self.debug_mode {
+            chip8_state.undo_log = Some(UndoLog::new(DEFAULT_UNDO_LOG_SIZE));
+            chip8_state.breakpoints = breakpoint::load(&self.breakpoints_path(), &self.labels)?;
+        }
+        self.load_rpl_flags(&mut chip8_state)?;
+        Ok(chip8_state)
+    }
+
+    /// Path of the file with the persisted RPL user flags for the ROM
+    pub fn rpl_flags_path(&self) -> PathBuf {
+        let rom = Path::new(&self.file);
+        let mut file_name = rom.file_name().unwrap_or_default().to_os_string();
+        file_name.push(".rpl");
+        match &self.rpl_dir {
+            Some(dir) => Path::new(dir).join(file_name),
+            None => rom.with_file_name(file_name),
+        }
+    }
+
+    fn load_rpl_flags(&self, chip8_state: &mut Chip8State) -> IoResult<()> {
+        match fs::read(self.rpl_flags_path()) {
+            Ok(flags) => {
+                let len = flags.len().min(chip8_state.rpl_flags.len());
+                chip8_state.rpl_flags[..len].copy_from_slice(&flags[..len]);
+                Ok(())
+            },
+            // Nothing was saved yet
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save_rpl_flags(&self, chip8_state: &Chip8State) -> IoResult<()> {
+        fs::write(self.rpl_flags_path(), chip8_state.rpl_flags)
+    }
+
+    /// Path of the save state in the given slot, next to the ROM
+    pub fn state_path(&self, slot: u8) -> PathBuf {
+        let rom = Path::new(&self.file);
+        let mut file_name = rom.file_name().unwrap_or_default().to_os_string();
+        file_name.push(format!(".state{}", slot));
+        rom.with_file_name(file_name)
+    }
+
+    /// Path of the file with the breakpoints set for the ROM, next to
+    /// the ROM
+    pub fn breakpoints_path(&self) -> PathBuf {
+        let rom = Path::new(&self.file);
+        let mut file_name = rom.file_name().unwrap_or_default().to_os_string();
+        file_name.push(".breakpoints.json");
+        rom.with_file_name(file_name)
+    }
+
+    pub fn save_breakpoints(&self, chip8_state: &Chip8State) -> IoResult<()> {
+        breakpoint::save(&chip8_state.breakpoints, &self.breakpoints_path())
+    }
+
+    pub fn save_state_file(&self, chip8_state: &Chip8State, path: &Path) -> IoResult<()> {
+        savestate::save(chip8_state, &self.rom_hash, path)
+    }
+
+    pub fn load_state_file(&self, chip8_state: &mut Chip8State, path: &Path) -> IoResult<()> {
+        savestate::load(chip8_state, &self.rom_hash, path)
+    }
+
+    /// Create the CHIP-8 and load the save state given on the command
+    /// line, if there is one
+    pub fn start_chip8(&self) -> IoResult<Chip8State> {
+        let mut chip8_state = self.create_chip8()?;
+        if let Some(path) = &self.load_state {
+            self.load_state_file(&mut chip8_state, Path::new(path))?;
+        }
         Ok(chip8_state)
     }
 }
@@ -0,0 +1,82 @@
+/// Sample rate of the generated audio
+pub const SAMPLE_RATE: u32 = 44100;
+pub const PATTERN_SIZE: usize = 16;
+pub const DEFAULT_PITCH: u8 = 64;
+/// Pattern played before a program loads its own one. Four bits on
+/// and four bits off give a 500Hz square wave at the default pitch.
+pub const DEFAULT_PATTERN: [u8; PATTERN_SIZE] = [0xf0; PATTERN_SIZE];
+const VOLUME: i16 = i16::MAX / 4;
+
+/// Rate in bits per second at which the XO-CHIP audio pattern is
+/// played for the given pitch register value
+/// https://johnearnest.github.io/Octo/docs/XO-ChipSpecification.html
+pub fn playback_rate(pitch: u8) -> f64 {
+    4000.0 * 2f64.powf((pitch as f64 - 64.0) / 48.0)
+}
+
+/// Turns the 1-bit XO-CHIP audio pattern into 16-bit mono samples.
+/// The position inside the pattern is kept between calls, so
+/// consecutive buffers join without clicks.
+#[derive(Clone, Default)]
+pub struct Synthesizer {
+    // Position in bits inside the pattern
+    position: f64,
+}
+
+impl Synthesizer {
+    pub fn new() -> Self {
+        Self {
+            position: 0.0,
+        }
+    }
+
+    pub fn render(&mut self, pattern: &[u8; PATTERN_SIZE], pitch: u8, samples: &mut [i16]) {
+        let pattern_bits = (PATTERN_SIZE * 8) as f64;
+        let step = playback_rate(pitch) / SAMPLE_RATE as f64;
+        for sample in samples.iter_mut() {
+            let bit = self.position as usize;
+            let set = (pattern[bit / 8] >> (7 - bit % 8)) & 1 == 1;
+            *sample = if set { VOLUME } else { -VOLUME };
+            self.position = (self.position + step) % pattern_bits;
+        }
+    }
+
+    /// Render a buffer that can be played in a loop. It holds a whole
+    /// number of pattern repetitions, around a quarter of a second
+    /// long, so the seam between loops is as small as possible.
+    pub fn render_loop(&mut self, pattern: &[u8; PATTERN_SIZE], pitch: u8) -> Vec<i16> {
+        let pattern_bits = (PATTERN_SIZE * 8) as f64;
+        let rate = playback_rate(pitch);
+        let repetitions = (0.25 * rate / pattern_bits).round().max(1.0);
+        let length = (repetitions * pattern_bits * SAMPLE_RATE as f64 / rate).round();
+        let mut samples = vec![0; length as usize];
+        self.position = 0.0;
+        self.render(pattern, pitch, &mut samples);
+        samples
+    }
+}
+
+/// Wrap mono samples into a WAV file, so they can be loaded by audio
+/// libraries that only take encoded files
+pub fn to_wav(samples: &[i16]) -> Vec<u8> {
+    let data_size = (samples.len() * 2) as u32;
+    let mut wav = Vec::with_capacity(44 + data_size as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_size).to_le_bytes());
+    wav.extend_from_slice(b"WAVE");
+    wav.extend_from_slice(b"fmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    // PCM, mono
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    wav.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_size.to_le_bytes());
+    for sample in samples {
+        wav.extend_from_slice(&sample.to_le_bytes());
+    }
+    wav
+}
@@ -3,6 +3,7 @@ use std::error::Error;
 use std::fs::File;
 use std::io::prelude::*;
 use yayachip8rsemu::disasm;
+use yayachip8rsemu::args::Chip8Quirks;
 
 #[derive(Parser, Debug)]
 #[command(author = "poni <poniponiponiponiponiponiponiponiponiponi@protonmail.com>")]
@@ -24,6 +25,10 @@ struct Args {
    /// Amount of instruction to print. Zero means to the end
    #[arg(short, long, default_value_t = 0)]
    instruction_amount: usize,
+
+   /// Platform whose instruction set is used for decoding
+   #[arg(value_enum, short, long, default_value_t = Chip8Quirks::Chip8)]
+   quirks: Chip8Quirks,
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -34,12 +39,14 @@ fn main() -> Result<(), Box<dyn Error>> {
     }
     
     let args = Args::parse();
+    let quirks_config = args.quirks.quirks_config();
     let mut file = File::open(args.file)?;
     let mut contents = Vec::<u8>::new();
     file.read_to_end(&mut contents)?;
 
     let mut instructions_printed = 0;
-    for i in (args.start..contents.len()).step_by(2) {
+    let mut i = args.start;
+    while i < contents.len() {
         instructions_printed += 1;
         if instructions_printed == args.instruction_amount {
             break;
@@ -48,11 +55,12 @@ fn main() -> Result<(), Box<dyn Error>> {
         if i+1 == contents.len() {
             break;
         }
-        let byte1 = contents[i];
-        let byte2 = contents[i+1];
-        let word = ((byte1 as u16) << 8) | byte2 as u16;
-        let instruction = disasm::Instruction::from(word);
-        println!("{:04x}:\t{:04x} {}", i, word, instruction);
+        let bytes = &contents[i..std::cmp::min(i+4, contents.len())];
+        let instruction = disasm::Instruction::decode(bytes, &quirks_config);
+        let size = instruction.size();
+        let hex: String = bytes[..size].iter().map(|b| format!("{:02x}", b)).collect();
+        println!("{:04x}:\t{} {}", i, hex, instruction);
+        i += size;
     }
 
     Ok(())
@@ -1,248 +1,197 @@
-use yayachip8rsemu::state::{Chip8State, QuirksConfig};
-use yayachip8rsemu::args::Args;
-use yayachip8rsemu::debug;
-use clap::{Parser, ValueEnum};
+use yayachip8rsemu::state::{Chip8State, Platform};
+use yayachip8rsemu::args::{Args, Cli};
+use yayachip8rsemu::debug::{self, DebugInput};
+use yayachip8rsemu::frontend::{Audio, Display};
+use yayachip8rsemu::gui::{MacroquadAudio, MacroquadDisplay, MacroquadInput, REWIND_KEY};
+use yayachip8rsemu::rewind::Rewind;
+use yayachip8rsemu::movie::{MoviePlayer, MovieRecorder};
+use clap::Parser;
 use macroquad::prelude::*;
-use macroquad::audio::{load_sound, play_sound_once};
-use std::time::SystemTime;
-use std::{thread, time};
+use std::path::Path;
+use std::time::Instant;
 use std::error::Error;
 
 #[derive(Parser, Debug)]
 #[command(author = "poni <poniponiponiponiponiponiponiponiponiponi@protonmail.com>")]
 #[command(about = "yayachip8rsemu", long_about = None)]
 #[command(version)]
-struct Cli {
-    /// ROM to run
-    #[arg(short, long)]
-    file: String,
-
-    /// Offset where to load the binary image in the CHIP-8 address space
-    #[arg(short, long, default_value_t = 0x200)]
-    offset: u16,
-
-    /// Start address of the execution
-    #[arg(long, default_value_t = 0x200)]
-    start: u16,
-
-    /// Pixel size
-    #[arg(short, long, default_value_t = 16)]
-    pixel_size: i32,
-
-    /// Start with stopped execution
-    #[arg(short, long, default_value_t = false)]
-    stop: bool,
-
-    /// Debug mode. Draw special debug windows
-    #[arg(short, long, default_value_t = false)]
-    debug_mode: bool,
-
-    /// Pick quirks
-    #[arg(value_enum, short, long, default_value_t = Chip8Quirks::Chip8)]
-    quirks: Chip8Quirks
-}
-
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
-enum Chip8Quirks {
-    Chip8,
-    SuperChip,
-    XoChip
+struct EmulatorCli {
+    #[command(flatten)]
+    cli: Cli,
 }
 
-impl Cli {
-    fn to_args(&self) -> Args {
-        Args {
-            file: self.file.clone(),
-            offset: self.offset,
-            start: self.start,
-            pixel_size: self.pixel_size,
-            stop: self.stop,
-            debug_mode: self.debug_mode,
-            quirks_config: match self.quirks {
-                Chip8Quirks::Chip8 => {
-                    QuirksConfig::get_chip8()
-                },
-                Chip8Quirks::XoChip => {
-                    QuirksConfig::get_xo_chip()
-                },
-                Chip8Quirks::SuperChip => {
-                    QuirksConfig::get_super_chip()
-                }
-            }
-        }
-    }
-}
-
-fn handle_input(chip8_state: &mut Chip8State) {
-    let keyboard_key_chip8_key_pairs = [
-        (KeyCode::Key1, 0x1),
-        (KeyCode::Key2, 0x2),
-        (KeyCode::Key3, 0x3),
-        (KeyCode::Key4, 0xc),
-        (KeyCode::Q, 0x4),
-        (KeyCode::W, 0x5),
-        (KeyCode::E, 0x6),
-        (KeyCode::R, 0xd),
-        (KeyCode::A, 0x7),
-        (KeyCode::S, 0x8),
-        (KeyCode::D, 0x9),
-        (KeyCode::F, 0xe),
-        (KeyCode::Z, 0xa),
-        (KeyCode::X, 0x0),
-        (KeyCode::C, 0xb),
-        (KeyCode::V, 0xf),
-    ];
-    
-    for (keyboard_key, chip8_key) in keyboard_key_chip8_key_pairs {
-        let is_pressed = is_key_down(keyboard_key);
-        if is_pressed && !chip8_state.key_pressed[chip8_key] {
-            // last pressed key
-            chip8_state.reg[chip8_state.keypress_reg as usize] = chip8_key as u8;
+/// Longest time the emulation catches up on at once, in seconds
+const MAX_CATCH_UP: f64 = 0.25;
+
+/// Keys of the save state slots 1 to 9. The key loads the slot, with
+/// Shift held it saves to it
+const STATE_SLOT_KEYS: [KeyCode; 9] = [
+    KeyCode::F1, KeyCode::F2, KeyCode::F3,
+    KeyCode::F4, KeyCode::F5, KeyCode::F6,
+    KeyCode::F7, KeyCode::F8, KeyCode::F9,
+];
+
+fn handle_state_slot_keys(chip8_state: &mut Chip8State, args: &Args) {
+    let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+    for (slot, &key) in (1..).zip(STATE_SLOT_KEYS.iter()) {
+        if !is_key_pressed(key) {
+            continue;
         }
-        chip8_state.key_pressed[chip8_key] = is_pressed;
-    }
-}
-
-fn draw_screen(chip8_state: &mut Chip8State, ps: usize) {
-    clear_background(DARKGRAY);
-    let mut last_pixel = (0, 0);
-    for (y, line) in chip8_state.screen.iter().enumerate() {
-        for (x, &pixel) in line.iter().enumerate() {
-            last_pixel = (x, y);
-            if pixel {
-                draw_rectangle(
-                    x as f32 * ps as f32,
-                    y as f32 * ps as f32,
-                    ps as f32,
-                    ps as f32,
-                    PURPLE
-                );
+        let path = args.state_path(slot);
+        if shift {
+            match args.save_state_file(chip8_state, &path) {
+                Ok(()) => eprintln!("Saved the state to slot {}", slot),
+                Err(e) => eprintln!("Error while saving the state: {}", e),
+            }
+        } else {
+            match args.load_state_file(chip8_state, &path) {
+                Ok(()) => eprintln!("Loaded the state from slot {}", slot),
+                Err(e) => eprintln!("Error while loading the state: {}", e),
             }
         }
-        
-        // Draw a line on the right side of the screen to designate
-        // an end of the screen
-        draw_rectangle(
-            (last_pixel.0 + 1) as f32 * ps as f32,
-            last_pixel.1 as f32 * ps as f32,
-            ps as f32,
-            ps as f32,
-            GRAY
-        );
-    }
-    
-    // Draw a line on the bottom of the screen
-    for x in 0..=last_pixel.0+1 {
-        draw_rectangle(
-            x as f32*ps as f32,
-            (last_pixel.1 + 1) as f32*ps as f32,
-            ps as f32,
-            ps as f32,
-            GRAY
-        );
     }
 }
 
-async fn main_loop(chip8_state: &mut Chip8State, args: &Args) {
-    let mut screen_timer = SystemTime::now();
-    let mut timer_timer = SystemTime::now();
-    // Later used so the excess in case when time_diff > 0 (instead of
-    // timer_diff == 0) doesn't go to waste
-    let mut screen_excess = 0.0;
-    let mut timer_excess = 0.0;
-
-    let sound = load_sound("./sound.ogg").await;
-    if let Err(e) = &sound {
-        eprintln!("Error while loading sound file: {}", e);
-    }
+async fn main_loop(chip8_state: &mut Chip8State, args: &Args) -> Result<(), Box<dyn Error>> {
+    let mut display = MacroquadDisplay {
+        palette: args.palette,
+        pixel_size: args.pixel_size,
+    };
+    let mut audio = MacroquadAudio::new(chip8_state.quirks_config.platform == Platform::XoChip).await;
+    let mut input = MacroquadInput::new(&args.key_map)?;
+    let mut frame_timer = Instant::now();
+    // Emulated frames that are due but weren't run yet. Keeps the
+    // fractions of a frame, so the speed doesn't drift
+    let mut frames_due = 0.0;
+    let mut rewind = Rewind::new(args.rewind_seconds as usize * 60);
+    rewind.push(chip8_state);
+
+    let mut recorder = match &args.record {
+        Some(_) => {
+            // Closing the window has to end the loop, so the movie
+            // gets saved
+            prevent_quit();
+            Some(MovieRecorder::new(chip8_state, &args.rom_hash))
+        },
+        None => None,
+    };
+    let mut player = match &args.play {
+        Some(path) => {
+            let player = MoviePlayer::load(Path::new(path), &args.rom_hash)?;
+            player.prepare(chip8_state)?;
+            Some(player)
+        },
+        None => None,
+    };
+    let instructions_per_frame = player.as_ref()
+        .map_or(args.instructions_per_frame, |player| player.instructions_per_frame());
 
     // Variables for the debug windows
-    let mut steps = String::new();
-    let mut breakpoint_addr = String::new();
-    let mut multiplier = String::new();
-    let mut snapshots = Vec::new();
+    let mut debug_input = DebugInput::default();
     // So called main execution loop
     loop {
-        // Hanlde input
-        handle_input(chip8_state);
+        // Hanlde input. Movies read the keys at the start of every
+        // frame instead, in the loop below
+        let movie = recorder.is_some() || player.is_some();
+        if !movie {
+            chip8_state.poll_input(&mut input);
+            // Jumping around in time would make the movie useless
+            handle_state_slot_keys(chip8_state, args);
+        }
 
-        // Handle all timers
-        // Sound timer
-        if chip8_state.sound_timer > 0 {
-            if let Ok(sound) = sound {
-                play_sound_once(sound);
+        // Run as many 60Hz frames as the time passed since the last
+        // iteration asks for. Don't try to catch up after long pauses,
+        // e.g. when the window was being dragged
+        let elapsed = frame_timer.elapsed().as_secs_f64().min(MAX_CATCH_UP);
+        frame_timer = Instant::now();
+        frames_due += elapsed * 60.0 * chip8_state.time_multiplier;
+        // Rewinding goes back one emulated frame for every frame that
+        // is due, whether the execution is stopped or not
+        if is_key_down(REWIND_KEY) && !movie {
+            while frames_due >= 1.0 {
+                frames_due -= 1.0;
+                rewind.rewind(chip8_state);
             }
+        } else if !chip8_state.is_running() {
+            frames_due = 0.0;
         }
-        // Update inner CHIP-8 timers every 1/60 second as defined in
-        // the technical reference
-        match timer_timer.elapsed() {
-            Ok(elapsed) => {
-                let secs = elapsed.as_secs_f64();
-                let time_diff = secs + timer_excess - (1.0 / 60.0 / chip8_state.time_multiplier);
-                if time_diff >= 0.0 {
-                    if chip8_state.delay_timer != 0 {
-                        chip8_state.delay_timer -= 1;
-                    }
-                    if chip8_state.sound_timer != 0 {
-                        chip8_state.sound_timer -= 1;
-                    }
-                    timer_excess = time_diff;
-                    timer_timer = SystemTime::now();
+        while frames_due >= 1.0 && chip8_state.is_running() {
+            frames_due -= 1.0;
+            // A frame of a movie has the same keys from start to end,
+            // also when a breakpoint stopped it in the middle
+            if chip8_state.frame_cycle == 0 {
+                match &mut player {
+                    Some(player) => chip8_state.poll_input(player),
+                    None if recorder.is_some() => chip8_state.poll_input(&mut input),
+                    None => {},
                 }
             }
-            Err(e) => {
-                eprintln!("Timer's timer error: {e:?}");
+            let frame = chip8_state.frames;
+            if let Err(e) = chip8_state.run_frame(instructions_per_frame) {
+                eprintln!("Execution halted: {}", e);
             }
-        }
-        
-        // Update screen every 1/60 second so we have 60 fps. Not
-        // defined in the technical reference
-        match screen_timer.elapsed() {
-            Ok(elapsed) => {
-                let secs = elapsed.as_secs_f64();
-                let time_diff = secs + screen_excess - (1.0 / 60.0);
-                if time_diff >= 0.0 {
-                    draw_screen(chip8_state, args.pixel_size as usize);
-                    if args.debug_mode {
-                        debug::debug_windows(
-                            chip8_state,
-                            &mut snapshots,
-                            &mut steps,
-                            &mut breakpoint_addr,
-                            &mut multiplier,
-                            args
-                        );
-                    }
-                    next_frame().await;
-                    screen_excess = time_diff;
-                    screen_timer = SystemTime::now();
+            if let Some(watch_hit) = &chip8_state.watch_hit {
+                eprintln!("{}", watch_hit);
+            }
+            // A breakpoint or a watchpoint can stop the execution in
+            // the middle of a frame, the history and the movie only keep
+            // whole ones
+            if chip8_state.frames == frame {
+                continue;
+            }
+            rewind.push(chip8_state);
+            if let Some(recorder) = &mut recorder {
+                recorder.record_frame(chip8_state);
+            }
+            if let Some(movie_player) = &mut player {
+                if let Err(e) = movie_player.check_frame(chip8_state) {
+                    eprintln!("{}", e);
+                    player = None;
+                } else if movie_player.is_finished() {
+                    eprintln!("The movie is over, the keyboard is used again");
+                    player = None;
                 }
             }
-            Err(e) => {
-                eprintln!("Screen's timer error: {e:?}");
+        }
+        if chip8_state.rpl_flags_changed {
+            if let Err(e) = args.save_rpl_flags(chip8_state) {
+                eprintln!("Error while saving RPL user flags: {}", e);
             }
+            chip8_state.rpl_flags_changed = false;
+        }
+        // In the debug mode keep the window open, so the final state
+        // can still be inspected
+        if chip8_state.exited && !args.debug_mode {
+            break;
+        }
+        if is_quit_requested() {
+            break;
         }
-        
-        // Handle emulation
-        chip8_state.emulate_instruction();
 
-        // CHIP-8 doesn't really have a set cpu frequency but
-        // according to a random reddit post some ROMs might be
-        // frequency sensitive, so a cpu clock around 1000hz seems
-        // like a nice middleground
-        // https://www.reddit.com/r/EmuDev/comments/gvmk12/comment/fsq9p8a/
-        let to_sleep = time::Duration::from_secs_f64(1.0/1000.0/chip8_state.time_multiplier);
-        thread::sleep(to_sleep);
+        // Timers are ticked by the emulated clock, here they are only
+        // turned into sound
+        audio.play(chip8_state.tone());
+        display.draw(&chip8_state.screen);
+        if args.debug_mode {
+            let movie = recorder.is_some() || player.is_some();
+            debug::debug_windows(chip8_state, &mut debug_input, movie, args);
+        }
+        next_frame().await;
+    }
+
+    if let (Some(recorder), Some(path)) = (&mut recorder, &args.record) {
+        recorder.save(chip8_state, Path::new(path))?;
+        eprintln!("Recorded {} frames to {}", recorder.frames(), path);
     }
+    Ok(())
 }
 
 #[macroquad::main("yayachip8rsemu")]
 async fn main() -> Result<(), Box<dyn Error>> {
-    let cli = Cli::parse();
-    let args = cli.to_args();
-    let mut chip8_state = args.create_chip8()?;
+    let args = EmulatorCli::parse().cli.to_args()?;
+    let mut chip8_state = args.start_chip8()?;
 
-    main_loop(&mut chip8_state, &args).await;
+    main_loop(&mut chip8_state, &args).await?;
 
     Ok(())
 }
@@ -0,0 +1,230 @@
+use clap::{Parser, ValueEnum};
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use yayachip8rsemu::args::{parse_addr, Args, Cli};
+use yayachip8rsemu::breakpoint;
+use yayachip8rsemu::frontend::Input;
+use yayachip8rsemu::movie::{MoviePlayer, MovieRecorder};
+use yayachip8rsemu::state::Chip8State;
+
+#[derive(Parser, Debug)]
+#[command(author = "poni <poniponiponiponiponiponiponiponiponiponi@protonmail.com>")]
+#[command(about = "yayachip8rsemu without a window, for automated runs", long_about = None)]
+#[command(version)]
+struct HeadlessCli {
+    #[command(flatten)]
+    cli: Cli,
+
+    /// Amount of 60Hz frames to run for. Defaults to 600, or to the
+    /// length of the movie given with --play
+    #[arg(long)]
+    frames: Option<u64>,
+
+    /// Stop as soon as the execution reaches this address
+    #[arg(long, value_parser = parse_addr)]
+    until_pc: Option<u16>,
+
+    /// Also stop at the breakpoints saved for the ROM in the debug
+    /// mode of the emulator. They are ignored otherwise
+    #[arg(long)]
+    breakpoints: bool,
+
+    /// Hold a key during the given frames, as FRAME:KEY or
+    /// FRAME:KEY:DURATION with the key in hex. Can be repeated
+    #[arg(long = "key", value_parser = parse_key_press)]
+    keys: Vec<KeyPress>,
+
+    /// Format of the screen dump
+    #[arg(value_enum, long, default_value_t = ScreenFormat::Ascii)]
+    format: ScreenFormat,
+
+    /// Where to write the screen dump. By default it's printed to
+    /// the standard output, before the state of the registers
+    #[arg(long)]
+    output: Option<String>,
+
+    /// Save the state at the end of the run, to be picked up later
+    /// with --load-state
+    #[arg(long)]
+    save_state: Option<String>,
+}
+
+const DEFAULT_FRAMES: u64 = 600;
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+enum ScreenFormat {
+    Ascii,
+    Pbm,
+    Png,
+}
+
+#[derive(Clone, Debug)]
+struct KeyPress {
+    frame: u64,
+    key: u8,
+    duration: u64,
+}
+
+fn parse_key_press(key_press: &str) -> Result<KeyPress, String> {
+    let parts: Vec<&str> = key_press.split(':').collect();
+    if parts.len() != 2 && parts.len() != 3 {
+        return Err(format!("{} is not FRAME:KEY or FRAME:KEY:DURATION", key_press));
+    }
+    let frame = parts[0].parse::<u64>()
+        .map_err(|e| format!("{} is not a valid frame: {}", parts[0], e))?;
+    let key = match u8::from_str_radix(parts[1], 16) {
+        Ok(key) if key < 16 => key,
+        _ => return Err(format!("{} is not a key between 0 and f", parts[1])),
+    };
+    let duration = match parts.get(2) {
+        Some(duration) => duration.parse::<u64>()
+            .map_err(|e| format!("{} is not a valid duration: {}", duration, e))?,
+        None => 1,
+    };
+    Ok(KeyPress { frame, key, duration })
+}
+
+/// Plays back the key presses given on the command line
+struct ScriptedInput {
+    key_presses: Vec<KeyPress>,
+    frame: u64,
+}
+
+impl Input for ScriptedInput {
+    fn keys(&mut self) -> [bool; 16] {
+        let mut keys = [false; 16];
+        for key_press in self.key_presses.iter() {
+            if (key_press.frame..key_press.frame + key_press.duration).contains(&self.frame) {
+                keys[key_press.key as usize] = true;
+            }
+        }
+        keys
+    }
+}
+
+fn write_screen(chip8_state: &Chip8State, args: &Args, format: ScreenFormat,
+                out: &mut dyn Write) -> Result<(), Box<dyn Error>> {
+    let screen = &chip8_state.screen;
+    match format {
+        ScreenFormat::Ascii => out.write_all(screen.to_ascii().as_bytes())?,
+        ScreenFormat::Pbm => out.write_all(screen.to_pbm().as_bytes())?,
+        ScreenFormat::Png => {
+            let mut encoder = png::Encoder::new(out, screen.width() as u32, screen.height() as u32);
+            encoder.set_color(png::ColorType::Rgb);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header()?;
+            writer.write_image_data(&screen.to_rgb(&args.palette))?;
+        },
+    }
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    // https://github.com/rust-lang/rust/issues/46016
+    #[cfg(unix)]
+    unsafe {
+        libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+    }
+
+    let headless_cli = HeadlessCli::parse();
+    if headless_cli.format == ScreenFormat::Png && headless_cli.output.is_none() {
+        return Err("PNG screen dumps need --output".into());
+    }
+    let args = headless_cli.cli.to_args()?;
+    let mut chip8_state = args.start_chip8()?;
+    chip8_state.breakpoints = if headless_cli.breakpoints {
+        breakpoint::load(&args.breakpoints_path(), &args.labels)?
+    } else {
+        Vec::new()
+    };
+    if let Some(until_pc) = headless_cli.until_pc {
+        chip8_state.add_breakpoint(until_pc);
+    }
+    if args.stop {
+        eprintln!("--stop has no effect in the headless mode");
+    }
+
+    let mut recorder = match &args.record {
+        Some(_) => Some(MovieRecorder::new(&chip8_state, &args.rom_hash)),
+        None => None,
+    };
+    let mut player = match &args.play {
+        Some(path) => {
+            let player = MoviePlayer::load(Path::new(path), &args.rom_hash)?;
+            player.prepare(&mut chip8_state)?;
+            Some(player)
+        },
+        None => None,
+    };
+    let instructions_per_frame = player.as_ref()
+        .map_or(args.instructions_per_frame, |player| player.instructions_per_frame());
+    let frames = headless_cli.frames
+        .unwrap_or_else(|| player.as_ref().map_or(DEFAULT_FRAMES, |player| player.len()));
+    let last_frame = chip8_state.frames + frames;
+
+    let mut input = ScriptedInput {
+        key_presses: headless_cli.keys.clone(),
+        frame: 0,
+    };
+    let mut halted = None;
+    let mut desync = None;
+    while chip8_state.frames < last_frame && chip8_state.is_running() {
+        input.frame = chip8_state.frames;
+        match &mut player {
+            Some(player) => chip8_state.poll_input(player),
+            None => chip8_state.poll_input(&mut input),
+        }
+        let frame = chip8_state.frames;
+        if let Err(e) = chip8_state.run_frame(instructions_per_frame) {
+            halted = Some(e);
+        }
+        // --until-pc can stop the execution in the middle of a frame,
+        // movies only have whole ones
+        if chip8_state.frames == frame {
+            continue;
+        }
+        if let Some(recorder) = &mut recorder {
+            recorder.record_frame(&chip8_state);
+        }
+        if let Some(player) = &mut player {
+            if let Err(e) = player.check_frame(&chip8_state) {
+                desync = Some(e);
+                break;
+            }
+        }
+    }
+    if let (Some(recorder), Some(path)) = (&mut recorder, &args.record) {
+        recorder.save(&chip8_state, Path::new(path))?;
+    }
+    if chip8_state.rpl_flags_changed {
+        args.save_rpl_flags(&chip8_state)?;
+    }
+
+    match &headless_cli.output {
+        Some(path) => {
+            let mut file = BufWriter::new(File::create(path)?);
+            write_screen(&chip8_state, &args, headless_cli.format, &mut file)?;
+            file.flush()?;
+        },
+        None => write_screen(&chip8_state, &args, headless_cli.format, &mut io::stdout())?,
+    }
+    print!("{}", chip8_state.get_state_string());
+    if let Some(path) = &headless_cli.save_state {
+        args.save_state_file(&chip8_state, Path::new(path))?;
+    }
+
+    if let Some(e) = desync {
+        return Err(e.into());
+    }
+    if let Some(e) = halted {
+        return Err(format!("Execution halted: {}", e).into());
+    }
+    if let Some(until_pc) = headless_cli.until_pc {
+        if chip8_state.pc != until_pc {
+            return Err(format!("{:#x} wasn't reached in {} frames", until_pc, frames).into());
+        }
+    }
+    Ok(())
+}
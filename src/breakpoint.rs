@@ -0,0 +1,370 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::args::parse_addr;
+use crate::state::Chip8State;
+
+/// Binary operators of the conditions, from the loosest binding group
+/// to the tightest
+const PRECEDENCE: [&[(&str, BinaryOp)]; 7] = [
+    &[("||", BinaryOp::Or)],
+    &[("&&", BinaryOp::And)],
+    &[
+        ("==", BinaryOp::Eq), ("!=", BinaryOp::Ne),
+        ("<=", BinaryOp::Le), (">=", BinaryOp::Ge),
+        ("<", BinaryOp::Lt), (">", BinaryOp::Gt),
+    ],
+    &[("|", BinaryOp::BitOr)],
+    &[("^", BinaryOp::BitXor)],
+    &[("&", BinaryOp::BitAnd)],
+    &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+];
+
+/// Every symbol a condition can contain, the longer ones first
+const SYMBOLS: [&str; 18] = [
+    "||", "&&", "==", "!=", "<=", ">=",
+    "<", ">", "|", "^", "&", "+", "-", "!", "[", "]", "(", ")",
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BinaryOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Add,
+    Sub,
+}
+
+/// Expression over the state of the machine. Comparisons and the
+/// logic operators give 1 or 0, anything that isn't 0 is true
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Expr {
+    Number(i64),
+    Reg(usize),
+    // The I register
+    Addr,
+    Pc,
+    DelayTimer,
+    SoundTimer,
+    // The byte at the address, [I] in the conditions
+    Memory(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Symbol(&'static str),
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start();
+    while !rest.is_empty() {
+        if let Some(&symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(*symbol)) {
+            tokens.push(Token::Symbol(symbol));
+            rest = &rest[symbol.len()..];
+        } else if rest.starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_') {
+            let end = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            let word = &rest[..end];
+            if word.starts_with(|c: char| c.is_ascii_digit()) {
+                tokens.push(Token::Number(parse_addr(word)? as i64));
+            } else {
+                tokens.push(Token::Name(word.to_owned()));
+            }
+            rest = &rest[end..];
+        } else {
+            return Err(format!("unexpected {:?} in the condition", rest.chars().next().unwrap()));
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent parser of the conditions
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    labels: &'a BTreeMap<String, u16>,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), String> {
+        match self.next() {
+            Some(Token::Symbol(found)) if found == symbol => Ok(()),
+            Some(token) => Err(format!("expected {} but found {:?}", symbol, token)),
+            None => Err(format!("expected {} but the condition ended", symbol)),
+        }
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr, String> {
+        let Some(operators) = PRECEDENCE.get(level) else {
+            return self.unary();
+        };
+        let mut left = self.binary(level + 1)?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Symbol(symbol)) => operators.iter()
+                    .find(|(op_symbol, _)| op_symbol == symbol)
+                    .map(|&(_, op)| op),
+                _ => None,
+            };
+            let Some(op) = op else {
+                return Ok(left);
+            };
+            self.pos += 1;
+            let right = self.binary(level + 1)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Symbol("!")) => Ok(Expr::Not(Box::new(self.unary()?))),
+            Some(Token::Symbol("(")) => {
+                let expr = self.binary(0)?;
+                self.expect(")")?;
+                Ok(expr)
+            },
+            Some(Token::Symbol("[")) => {
+                let expr = self.binary(0)?;
+                self.expect("]")?;
+                Ok(Expr::Memory(Box::new(expr)))
+            },
+            Some(Token::Number(number)) => Ok(Expr::Number(number)),
+            Some(Token::Name(name)) => self.name(&name),
+            Some(token) => Err(format!("unexpected {:?}", token)),
+            None => Err("the condition ended too early".to_owned()),
+        }
+    }
+
+    fn name(&self, name: &str) -> Result<Expr, String> {
+        let upper = name.to_ascii_uppercase();
+        match upper.as_str() {
+            "I" => return Ok(Expr::Addr),
+            "PC" => return Ok(Expr::Pc),
+            "DT" => return Ok(Expr::DelayTimer),
+            "ST" => return Ok(Expr::SoundTimer),
+            _ => {},
+        }
+        if let Some(reg) = upper.strip_prefix('V') {
+            if let Ok(reg) = usize::from_str_radix(reg, 16) {
+                if reg < 16 && upper.len() == 2 {
+                    return Ok(Expr::Reg(reg));
+                }
+            }
+        }
+        match self.labels.get(name) {
+            Some(&addr) => Ok(Expr::Number(addr as i64)),
+            None => Err(format!("{} is neither a register nor a known label", name)),
+        }
+    }
+}
+
+impl Expr {
+    pub fn parse(text: &str, labels: &BTreeMap<String, u16>) -> Result<Self, String> {
+        let mut parser = Parser { tokens: tokenize(text)?, pos: 0, labels };
+        let expr = parser.binary(0)?;
+        match parser.next() {
+            None => Ok(expr),
+            Some(token) => Err(format!("unexpected {:?} after the end of the condition", token)),
+        }
+    }
+
+    pub fn eval(&self, state: &Chip8State) -> i64 {
+        match self {
+            Self::Number(number) => *number,
+            Self::Reg(reg) => state.reg[*reg] as i64,
+            Self::Addr => state.addr as i64,
+            Self::Pc => state.pc as i64,
+            Self::DelayTimer => state.delay_timer as i64,
+            Self::SoundTimer => state.sound_timer as i64,
+            Self::Memory(addr) => {
+                let memory = &state.memory.memory;
+                let addr = addr.eval(state).rem_euclid(memory.len() as i64);
+                memory[addr as usize] as i64
+            },
+            Self::Not(expr) => (expr.eval(state) == 0) as i64,
+            Self::Binary(op, left, right) => {
+                let left = left.eval(state);
+                // Like in C the logic operators don't evaluate the right
+                // side when the left one decides
+                match op {
+                    BinaryOp::Or if left != 0 => return 1,
+                    BinaryOp::And if left == 0 => return 0,
+                    _ => {},
+                }
+                let right = right.eval(state);
+                match op {
+                    BinaryOp::Or | BinaryOp::And => (right != 0) as i64,
+                    BinaryOp::Eq => (left == right) as i64,
+                    BinaryOp::Ne => (left != right) as i64,
+                    BinaryOp::Lt => (left < right) as i64,
+                    BinaryOp::Le => (left <= right) as i64,
+                    BinaryOp::Gt => (left > right) as i64,
+                    BinaryOp::Ge => (left >= right) as i64,
+                    BinaryOp::BitOr => left | right,
+                    BinaryOp::BitXor => left ^ right,
+                    BinaryOp::BitAnd => left & right,
+                    BinaryOp::Add => left.wrapping_add(right),
+                    BinaryOp::Sub => left.wrapping_sub(right),
+                }
+            },
+        }
+    }
+}
+
+/// A condition together with the text it was parsed from
+#[derive(Clone, Debug)]
+pub struct Condition {
+    text: String,
+    expr: Expr,
+}
+
+impl Condition {
+    pub fn parse(text: &str, labels: &BTreeMap<String, u16>) -> Result<Self, String> {
+        Ok(Self {
+            text: text.trim().to_owned(),
+            expr: Expr::parse(text, labels)?,
+        })
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn is_true(&self, state: &Chip8State) -> bool {
+        self.expr.eval(state) != 0
+    }
+}
+
+/// Parse a breakpoint address given as a number or as a label
+pub fn parse_location(location: &str, labels: &BTreeMap<String, u16>) -> Result<u16, String> {
+    let location = location.trim();
+    match labels.get(location) {
+        Some(&addr) => Ok(addr),
+        None => parse_addr(location)
+            .map_err(|e| format!("{} (and there's no such label)", e)),
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Breakpoint {
+    pub addr: u16,
+    pub condition: Option<Condition>,
+    pub enabled: bool,
+    // Times the execution got to the breakpoint with the condition true
+    pub hit_count: u32,
+    // Hits to let through before stopping
+    pub ignore_count: u32,
+}
+
+impl Breakpoint {
+    pub fn new(addr: u16) -> Self {
+        Self {
+            addr,
+            condition: None,
+            enabled: true,
+            hit_count: 0,
+            ignore_count: 0,
+        }
+    }
+
+    /// Whether the execution is at the breakpoint and the condition is
+    /// true. The ignore count isn't taken into account
+    pub fn matches(&self, state: &Chip8State) -> bool {
+        self.enabled
+            && self.addr == state.pc
+            && self.condition.as_ref().is_none_or(|condition| condition.is_true(state))
+    }
+
+    pub fn describe(&self) -> String {
+        let mut description = format!("{:#06x}", self.addr);
+        if let Some(condition) = &self.condition {
+            description += &format!(" if {}", condition.text());
+        }
+        description += &format!(" hits: {}", self.hit_count);
+        if self.ignore_count > 0 {
+            description += &format!(" ignore: {}", self.ignore_count);
+        }
+        if !self.enabled {
+            description += " (disabled)";
+        }
+        description
+    }
+}
+
+/// How a breakpoint is saved. The hit count only lasts for a session
+#[derive(Serialize, Deserialize)]
+struct SavedBreakpoint {
+    addr: u16,
+    #[serde(default)]
+    condition: Option<String>,
+    enabled: bool,
+    #[serde(default)]
+    ignore_count: u32,
+}
+
+/// Write the breakpoints to a JSON file
+pub fn save(breakpoints: &[Breakpoint], path: &Path) -> IoResult<()> {
+    let saved: Vec<SavedBreakpoint> = breakpoints.iter()
+        .map(|breakpoint| SavedBreakpoint {
+            addr: breakpoint.addr,
+            condition: breakpoint.condition.as_ref().map(|condition| condition.text().to_owned()),
+            enabled: breakpoint.enabled,
+            ignore_count: breakpoint.ignore_count,
+        })
+        .collect();
+    let json = serde_json::to_string_pretty(&saved)
+        .map_err(|e| IoError::new(ErrorKind::InvalidData, e))?;
+    fs::write(path, json)
+}
+
+/// Read the breakpoints saved by `save`. No file means no breakpoints
+pub fn load(path: &Path, labels: &BTreeMap<String, u16>) -> IoResult<Vec<Breakpoint>> {
+    let json = match fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let invalid = |message: String| IoError::new(
+        ErrorKind::InvalidData, format!("{}: {}", path.display(), message)
+    );
+    let saved: Vec<SavedBreakpoint> = serde_json::from_str(&json)
+        .map_err(|e| invalid(e.to_string()))?;
+    saved.into_iter()
+        .map(|saved| Ok(Breakpoint {
+            addr: saved.addr,
+            condition: saved.condition
+                .map(|text| Condition::parse(&text, labels))
+                .transpose()
+                .map_err(invalid)?,
+            enabled: saved.enabled,
+            hit_count: 0,
+            ignore_count: saved.ignore_count,
+        }))
+        .collect()
+}
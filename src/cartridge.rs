@@ -0,0 +1,161 @@
+use std::env;
+use std::fs;
+use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use serde::Deserialize;
+use crate::args::Chip8Quirks;
+use crate::screen::{parse_palette, Palette};
+
+/// Compiler used for the source code in cartridges, unless the
+/// OCTO_CLI environment variable names another one
+const DEFAULT_OCTO_CLI: &str = "octo-cli";
+
+/// Compilations done so far, so each one gets its own temporary files
+static COMPILATIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// The compiler named by the OCTO_CLI environment variable, or the
+/// default one
+pub fn default_compiler() -> String {
+    env::var("OCTO_CLI").unwrap_or_else(|_| DEFAULT_OCTO_CLI.to_owned())
+}
+
+/// An Octo cartridge: a GIF whose pixels carry the source code and the
+/// options of the program. The low two bits of every pixel's color
+/// index hold the payload, four pixels per byte with the most
+/// significant bits first, continuing over all the frames. The payload
+/// is a big endian 32 bit length followed by a JSON object with the
+/// "program" and the "options".
+#[derive(Deserialize, Debug)]
+pub struct Cartridge {
+    // Octo source code
+    pub program: String,
+    #[serde(default)]
+    pub options: OctoOptions,
+}
+
+/// The options of Octo that matter for running the program
+#[derive(Deserialize, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct OctoOptions {
+    pub tickrate: Option<u32>,
+    pub max_size: Option<u32>,
+    pub background_color: Option<String>,
+    pub fill_color: Option<String>,
+    pub fill_color2: Option<String>,
+    pub blend_color: Option<String>,
+    pub shift_quirks: Option<bool>,
+    pub load_store_quirks: Option<bool>,
+    pub clip_quirks: Option<bool>,
+    pub v_blank_quirks: Option<bool>,
+    pub jump_quirks: Option<bool>,
+    pub logic_quirks: Option<bool>,
+}
+
+impl OctoOptions {
+    /// Octo has no notion of a platform, the memory size says which
+    /// one the program was written for
+    pub fn preset(&self) -> Option<Chip8Quirks> {
+        match self.max_size? {
+            3216 => Some(Chip8Quirks::Chip8),
+            3583 => Some(Chip8Quirks::SuperChip),
+            65024 => Some(Chip8Quirks::XoChip),
+            _ => None,
+        }
+    }
+
+    /// The quirks with the names used by QuirksConfig::set_quirk
+    pub fn quirks(&self) -> Vec<(String, bool)> {
+        [
+            ("shifting", self.shift_quirks),
+            ("memory", self.load_store_quirks.map(|unchanged| !unchanged)),
+            ("clipping", self.clip_quirks),
+            ("display_wait", self.v_blank_quirks),
+            ("jumping", self.jump_quirks),
+            ("vf_reset", self.logic_quirks),
+        ].into_iter()
+            .filter_map(|(name, value)| value.map(|value| (name.to_owned(), value)))
+            .collect()
+    }
+
+    /// The palette, if the cartridge has all four colors
+    pub fn palette(&self) -> Option<Palette> {
+        let colors = [&self.background_color, &self.fill_color, &self.fill_color2, &self.blend_color];
+        let colors: Option<Vec<&str>> = colors.iter()
+            .map(|color| color.as_deref())
+            .collect();
+        parse_palette(&colors?.join(",")).ok()
+    }
+}
+
+pub fn is_cartridge(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a")
+}
+
+fn invalid_cartridge(message: String) -> IoError {
+    IoError::new(ErrorKind::InvalidData, format!("invalid Octo cartridge: {}", message))
+}
+
+impl Cartridge {
+    pub fn decode(bytes: &[u8]) -> IoResult<Self> {
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::Indexed);
+        let mut decoder = options.read_info(bytes)
+            .map_err(|e| invalid_cartridge(e.to_string()))?;
+        let mut bits = Vec::new();
+        while let Some(frame) = decoder.read_next_frame()
+            .map_err(|e| invalid_cartridge(e.to_string()))? {
+            bits.extend(frame.buffer.iter().map(|pixel| pixel & 3));
+        }
+
+        let mut payload = bits.chunks_exact(4)
+            .map(|chunk| chunk.iter().fold(0, |byte, bits| byte << 2 | bits));
+        let length = payload.by_ref().take(4)
+            .fold(0, |length, byte| length << 8 | byte as usize);
+        // The JSON is written one byte per character
+        let json: String = payload.take(length).map(char::from).collect();
+        if json.len() != length {
+            return Err(invalid_cartridge(format!(
+                "payload is {} bytes long, but the image only holds {}", length, json.len()
+            )));
+        }
+        serde_json::from_str(&json).map_err(|e| invalid_cartridge(e.to_string()))
+    }
+
+    /// Assemble the source code with the given Octo command line
+    /// compiler
+    pub fn compile_with(&self, octo_cli: &str) -> IoResult<Vec<u8>> {
+        // A fresh directory, so nobody else can have put files in it.
+        // Names that are already taken are skipped
+        let dir = loop {
+            let dir = env::temp_dir().join(format!(
+                "yayachip8rsemu-cartridge-{}-{}",
+                std::process::id(), COMPILATIONS.fetch_add(1, Ordering::Relaxed)
+            ));
+            match fs::create_dir(&dir) {
+                Ok(()) => break dir,
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        };
+        let source = dir.join("program.8o");
+        let rom = dir.join("program.ch8");
+        fs::write(&source, &self.program)?;
+
+        let output = Command::new(octo_cli).arg(&source).arg(&rom).output();
+        let result = match output {
+            Ok(output) if output.status.success() => fs::read(&rom),
+            Ok(output) => Err(IoError::new(ErrorKind::InvalidData, format!(
+                "{} couldn't compile the cartridge: {}",
+                octo_cli, String::from_utf8_lossy(&output.stderr).trim()
+            ))),
+            Err(e) => Err(IoError::new(e.kind(), format!(
+                "cartridges contain Octo source code, which needs {} (or --octo-cli) to be compiled: {}",
+                octo_cli, e
+            ))),
+        };
+        // Failing to clean up the temporary files doesn't matter
+        let _ = fs::remove_dir_all(&dir);
+        result
+    }
+}
@@ -0,0 +1,137 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+use std::path::{Path, PathBuf};
+use serde::Deserialize;
+use crate::args::{parse_addr, Chip8Quirks};
+use crate::screen::{parse_palette, Palette};
+
+/// Settings for a single ROM, read from a TOML or JSON file. Every
+/// field is optional, the missing ones fall back to the defaults.
+///
+/// ```toml
+/// quirks = "super-chip"
+/// instructions_per_frame = 30
+/// offset = "0x200"
+/// palette = ["000000", "ffffff", "ff0000", "00ff00"]
+///
+/// [quirk]
+/// shifting = false
+///
+/// [keys]
+/// 5 = "Up"
+/// 8 = "Down"
+///
+/// [labels]
+/// main = "0x200"
+/// draw_player = 0x2a4
+/// ```
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RomConfig {
+    pub quirks: Option<Chip8Quirks>,
+    // Individual quirks set on top of the preset
+    #[serde(default)]
+    pub quirk: BTreeMap<String, bool>,
+    pub instructions_per_frame: Option<u32>,
+    pub offset: Option<Address>,
+    pub palette: Option<Vec<String>>,
+    // CHIP-8 key in hex to the name of the keyboard key
+    #[serde(default)]
+    pub keys: BTreeMap<String, String>,
+    // Names for addresses, usable in breakpoints
+    #[serde(default)]
+    pub labels: BTreeMap<String, Address>,
+}
+
+/// An address given either as a number or as a string like "0x200"
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum Address {
+    Number(u16),
+    Text(String),
+}
+
+impl Address {
+    pub fn value(&self) -> Result<u16, String> {
+        match self {
+            Self::Number(addr) => Ok(*addr),
+            Self::Text(addr) => parse_addr(addr),
+        }
+    }
+}
+
+impl RomConfig {
+    /// Read a config file. JSON is used for .json files, TOML for
+    /// everything else
+    pub fn load(path: &Path) -> IoResult<Self> {
+        let text = fs::read_to_string(path)?;
+        let parsed = if path.extension().is_some_and(|ext| ext == "json") {
+            serde_json::from_str(&text).map_err(|e| e.to_string())
+        } else {
+            toml::from_str(&text).map_err(|e| e.to_string())
+        };
+        parsed.map_err(|e| IoError::new(
+            ErrorKind::InvalidData, format!("{}: {}", path.display(), e)
+        ))
+    }
+
+    /// Look for <rom>.toml or <rom>.json next to the ROM. Not finding
+    /// any of them isn't an error
+    pub fn find_for_rom(rom: &str) -> IoResult<Option<Self>> {
+        for extension in ["toml", "json"] {
+            let path = config_path(rom, extension);
+            if path.exists() {
+                return Self::load(&path).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn offset(&self) -> IoResult<Option<u16>> {
+        self.offset.as_ref()
+            .map(|offset| offset.value().map_err(invalid_config))
+            .transpose()
+    }
+
+    pub fn palette(&self) -> IoResult<Option<Palette>> {
+        self.palette.as_ref()
+            .map(|colors| parse_palette(&colors.join(",")).map_err(invalid_config))
+            .transpose()
+    }
+
+    /// The key map with the CHIP-8 keys parsed
+    pub fn key_map(&self) -> IoResult<BTreeMap<u8, String>> {
+        let mut key_map = BTreeMap::new();
+        for (chip8_key, keyboard_key) in self.keys.iter() {
+            match u8::from_str_radix(chip8_key, 16) {
+                Ok(chip8_key) if chip8_key < 16 => {
+                    key_map.insert(chip8_key, keyboard_key.clone());
+                },
+                _ => return Err(invalid_config(
+                    format!("{} is not a CHIP-8 key between 0 and f", chip8_key)
+                )),
+            }
+        }
+        Ok(key_map)
+    }
+
+    /// The labels with their addresses parsed
+    pub fn labels(&self) -> IoResult<BTreeMap<String, u16>> {
+        self.labels.iter()
+            .map(|(label, addr)| Ok((label.clone(), addr.value().map_err(invalid_config)?)))
+            .collect()
+    }
+}
+
+fn config_path(rom: &str, extension: &str) -> PathBuf {
+    let rom = Path::new(rom);
+    let mut file_name = rom.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".");
+    file_name.push(extension);
+    rom.with_file_name(file_name)
+}
+
+fn invalid_config(message: String) -> IoError {
+    IoError::new(ErrorKind::InvalidData, format!("invalid ROM config: {}", message))
+}
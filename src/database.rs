@@ -0,0 +1,198 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+use std::path::Path;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use sha1::{Digest, Sha1};
+use crate::args::Chip8Quirks;
+use crate::screen::{parse_palette, Palette, DEFAULT_PALETTE};
+
+/// ROM database in the format of the community chip-8-database: a
+/// directory with programs.json, listing the known ROMs by their SHA-1 hash, and
+/// platforms.json, with the quirks of every platform.
+pub struct Database {
+    programs: Vec<Program>,
+    platforms: Vec<PlatformEntry>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Program {
+    title: String,
+    #[serde(default)]
+    roms: BTreeMap<String, RomEntry>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct RomEntry {
+    // Platforms the ROM runs on, the preferred one first
+    #[serde(default)]
+    platforms: Vec<String>,
+    // Quirks the ROM needs which are different from its platform's
+    #[serde(default)]
+    quirky_platforms: BTreeMap<String, DatabaseQuirks>,
+    tickrate: Option<u32>,
+    start_address: Option<u16>,
+    colors: Option<Colors>,
+    // Name of the action, like "up" or "a", to the CHIP-8 key
+    #[serde(default)]
+    keys: BTreeMap<String, u8>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Colors {
+    #[serde(default)]
+    pixels: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PlatformEntry {
+    id: String,
+    name: String,
+    default_tickrate: Option<u32>,
+    #[serde(default)]
+    quirks: DatabaseQuirks,
+}
+
+#[derive(Deserialize, Default, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+struct DatabaseQuirks {
+    shift: Option<bool>,
+    memory_leave_i_unchanged: Option<bool>,
+    wrap: Option<bool>,
+    jump: Option<bool>,
+    vblank: Option<bool>,
+    logic: Option<bool>,
+}
+
+impl DatabaseQuirks {
+    /// The quirks with the names used by QuirksConfig::set_quirk
+    fn overrides(&self) -> Vec<(String, bool)> {
+        [
+            ("shifting", self.shift),
+            ("memory", self.memory_leave_i_unchanged.map(|unchanged| !unchanged)),
+            ("clipping", self.wrap.map(|wrap| !wrap)),
+            ("jumping", self.jump),
+            ("display_wait", self.vblank),
+            ("vf_reset", self.logic),
+        ].into_iter()
+            .filter_map(|(name, value)| value.map(|value| (name.to_owned(), value)))
+            .collect()
+    }
+}
+
+/// Settings picked for a ROM found in the database
+#[derive(Debug)]
+pub struct DatabaseMatch {
+    pub title: String,
+    pub platform: String,
+    pub preset: Chip8Quirks,
+    // Quirks of the platform and of the ROM, applied on top of the
+    // preset
+    pub quirks: Vec<(String, bool)>,
+    pub instructions_per_frame: Option<u32>,
+    pub offset: Option<u16>,
+    pub palette: Option<Palette>,
+    pub key_map: BTreeMap<u8, String>,
+}
+
+impl DatabaseMatch {
+    /// Short description of the entry for the debug UI
+    pub fn describe(&self) -> String {
+        format!("{} ({})", self.title, self.platform)
+    }
+}
+
+/// Lowercase hex SHA-1 of the ROM, as used by the database
+pub fn rom_hash(rom: &[u8]) -> String {
+    Sha1::digest(rom).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// The preset closest to a platform of the database. None for the
+/// platforms that can't be emulated
+fn preset_for_platform(id: &str) -> Option<Chip8Quirks> {
+    match id {
+        "originalChip8" | "hybridVIP" | "modernChip8" => Some(Chip8Quirks::Chip8),
+        "chip48" | "superchip1" | "superchip" => Some(Chip8Quirks::SuperChip),
+        "xochip" => Some(Chip8Quirks::XoChip),
+        _ => None,
+    }
+}
+
+/// Keyboard key suggested for the actions the database knows about
+fn keyboard_key_for_action(action: &str) -> Option<&'static str> {
+    match action {
+        "up" => Some("Up"),
+        "down" => Some("Down"),
+        "left" => Some("Left"),
+        "right" => Some("Right"),
+        "a" => Some("Space"),
+        "b" => Some("LeftShift"),
+        _ => None,
+    }
+}
+
+fn load_json<T: DeserializeOwned>(path: &Path) -> IoResult<T> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| IoError::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+    serde_json::from_str(&text).map_err(|e| IoError::new(
+        ErrorKind::InvalidData, format!("{}: {}", path.display(), e)
+    ))
+}
+
+impl Database {
+    pub fn load(dir: &Path) -> IoResult<Self> {
+        Ok(Self {
+            programs: load_json(&dir.join("programs.json"))?,
+            platforms: load_json(&dir.join("platforms.json"))?,
+        })
+    }
+
+    /// Find the ROM by its hash. None if the ROM isn't known or it
+    /// only runs on platforms that aren't supported
+    pub fn lookup(&self, rom: &[u8]) -> Option<DatabaseMatch> {
+        let hash = rom_hash(rom);
+        let (program, entry) = self.programs.iter()
+            .find_map(|program| program.roms.get(&hash).map(|entry| (program, entry)))?;
+        let (platform_id, preset) = entry.platforms.iter()
+            .find_map(|id| preset_for_platform(id).map(|preset| (id, preset)))?;
+        let platform = self.platforms.iter().find(|platform| &platform.id == platform_id);
+
+        let mut quirks = platform.map(|platform| platform.quirks.overrides()).unwrap_or_default();
+        if let Some(rom_quirks) = entry.quirky_platforms.get(platform_id) {
+            quirks.extend(rom_quirks.overrides());
+        }
+
+        let palette = entry.colors.as_ref()
+            .filter(|colors| !colors.pixels.is_empty())
+            .and_then(|colors| {
+                // Fill the missing colors from the default palette
+                let colors: Vec<String> = (0..DEFAULT_PALETTE.len())
+                    .map(|i| colors.pixels.get(i).cloned()
+                        .unwrap_or_else(|| format!("{:06x}", DEFAULT_PALETTE[i])))
+                    .collect();
+                parse_palette(&colors.join(",")).ok()
+            });
+
+        let key_map = entry.keys.iter()
+            .filter(|(_, &chip8_key)| chip8_key < 16)
+            .filter_map(|(action, &chip8_key)| {
+                keyboard_key_for_action(action).map(|key| (chip8_key, key.to_owned()))
+            })
+            .collect();
+
+        Some(DatabaseMatch {
+            title: program.title.clone(),
+            platform: platform.map_or_else(|| platform_id.clone(), |platform| platform.name.clone()),
+            preset,
+            quirks,
+            instructions_per_frame: entry.tickrate
+                .or(platform.and_then(|platform| platform.default_tickrate)),
+            offset: entry.start_address,
+            palette,
+            key_map,
+        })
+    }
+}
@@ -2,6 +2,8 @@ use macroquad::prelude::*;
 use macroquad::ui::{hash, root_ui, widgets, Ui};
 use crate::state::Chip8State;
 use crate::args::Args;
+use crate::breakpoint::{parse_location, Breakpoint, Condition};
+use crate::watchpoint::{Access, Watchpoint};
 
 fn print_ui_text(ui: &mut Ui, str: String) {
     for line in str.lines() {
@@ -13,6 +15,7 @@ fn debug_window_execution(
     ui: &mut Ui,
     chip8_state: &mut Chip8State,
     steps: &mut String,
+    movie: bool,
     args: &Args
 ) {
     if ui.button(None, "Stop") {
@@ -23,6 +26,14 @@ fn debug_window_execution(
     if ui.button(None, "Continue") {
         chip8_state.continue_execution();
     }
+
+    // Jumping around in time would make the movie useless
+    if movie {
+        ui.label(None, "Restart and stepping are off");
+        ui.label(None, "while a movie is recorded");
+        ui.label(None, "or played");
+        return;
+    }
     
     ui.same_line(0.0);
     if ui.button(None, "Restart") {
@@ -45,6 +56,13 @@ fn debug_window_execution(
             ui.same_line(0.0);
         }
         ui.separator();
+        for step in [1, 10, 100] {
+            if ui.button(None, format!("Back {}", step)) {
+                chip8_state.step_back_n(step);
+            }
+            ui.same_line(0.0);
+        }
+        ui.separator();
         ui.label(None, "Make X amount of steps: ");
         ui.input_text(hash!(), "< --", steps);
         ui.separator();
@@ -56,24 +74,69 @@ fn debug_window_execution(
                 eprintln!("steps is not a number");
             }
         }
+        ui.same_line(0.0);
+        if ui.button(None, "Step back X") {
+            let steps = steps.parse::<u16>();
+            if let Ok(steps) = steps {
+                chip8_state.step_back_n(steps);
+            } else {
+                eprintln!("steps is not a number");
+            }
+        }
+        ui.separator();
+        if ui.button(None, "Reverse continue") {
+            chip8_state.reverse_continue();
+        }
+        let undoable = chip8_state.undo_log.as_ref().map_or(0, |undo_log| undo_log.len());
+        ui.label(None, &format!("Can step back {} instructions", undoable));
     });    
 }
 
+/// Text fields of a new breakpoint in the debug window
+#[derive(Default)]
+pub struct BreakpointInput {
+    // Address as a number or a label from the ROM config
+    pub location: String,
+    pub condition: String,
+    pub ignore_count: String,
+}
+
+impl BreakpointInput {
+    fn to_breakpoint(&self, args: &Args) -> Result<Breakpoint, String> {
+        let mut breakpoint = Breakpoint::new(parse_location(&self.location, &args.labels)?);
+        if !self.condition.trim().is_empty() {
+            breakpoint.condition = Some(Condition::parse(&self.condition, &args.labels)?);
+        }
+        if !self.ignore_count.trim().is_empty() {
+            breakpoint.ignore_count = self.ignore_count.trim().parse::<u32>()
+                .map_err(|e| format!("ignore count is not a number: {}", e))?;
+        }
+        Ok(breakpoint)
+    }
+}
+
 fn debug_window_breakpoints(
     ui: &mut Ui,
     chip8_state: &mut Chip8State,
-    breakpoint_addr: &mut String,
+    breakpoint_input: &mut BreakpointInput,
+    args: &Args
 ) {
     ui.tree_node(hash!(), "Breakpoints", |ui| {
-        ui.label(None, "Breakpoint address: ");
-        ui.input_text(hash!(), "< --", breakpoint_addr);
+        ui.label(None, "Address or label: ");
+        ui.input_text(hash!(), "< --", &mut breakpoint_input.location);
+        ui.label(None, "Condition, e.g. V3 == 0x10 && [I] != 0: ");
+        ui.input_text(hash!(), "< --", &mut breakpoint_input.condition);
+        ui.label(None, "Ignore count: ");
+        ui.input_text(hash!(), "< --", &mut breakpoint_input.ignore_count);
         ui.separator();
+        let mut changed = false;
         if ui.button(None, "Add breakpoint") {
-            let breakpoint_addr = breakpoint_addr.parse::<u16>();
-            if let Ok(breakpoint_addr) = breakpoint_addr {
-                chip8_state.add_breakpoint(breakpoint_addr);
-            } else {
-                eprintln!("breakpoint address is not an address");
+            match breakpoint_input.to_breakpoint(args) {
+                Ok(breakpoint) => {
+                    chip8_state.breakpoints.push(breakpoint);
+                    changed = true;
+                },
+                Err(e) => eprintln!("Invalid breakpoint: {}", e),
             }
         }
         
@@ -82,10 +145,97 @@ fn debug_window_breakpoints(
         // Not using an iterator since then I would have two borrows,
         // one mutable when removing
         for i in 0..chip8_state.breakpoints.len() {
-            ui.label(None, &format!("{i:2}: {:#06x}", chip8_state.breakpoints[i].addr));
+            ui.label(None, &format!("{i:2}: {}", chip8_state.breakpoints[i].describe()));
+            let toggle = if chip8_state.breakpoints[i].enabled { "Disable" } else { "Enable" };
+            if ui.button(None, toggle) {
+                chip8_state.breakpoints[i].enabled = !chip8_state.breakpoints[i].enabled;
+                changed = true;
+            }
+            ui.same_line(0.0);
+            if ui.button(None, "Reset hits") {
+                chip8_state.breakpoints[i].hit_count = 0;
+            }
             ui.same_line(0.0);
             if ui.button(None, "Remove") {
                 chip8_state.breakpoints.remove(i);
+                changed = true;
+                // Break to avoid mismatched lengths after removing
+                break;
+            }
+            ui.separator();
+        }
+        // Keep the saved breakpoints in sync, so they are back in the
+        // next session
+        if changed {
+            if let Err(e) = args.save_breakpoints(chip8_state) {
+                eprintln!("Error while saving breakpoints: {}", e);
+            }
+        }
+    });
+}
+
+/// Fields of a new watchpoint in the debug window
+#[derive(Default)]
+pub struct WatchpointInput {
+    pub start: String,
+    // Empty to watch a single address
+    pub end: String,
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+}
+
+impl WatchpointInput {
+    fn to_watchpoint(&self, args: &Args) -> Result<Watchpoint, String> {
+        let start = parse_location(&self.start, &args.labels)?;
+        let end = if self.end.trim().is_empty() {
+            start
+        } else {
+            parse_location(&self.end, &args.labels)?
+        };
+        let accesses: Vec<Access> = [
+            (self.read, Access::Read), (self.write, Access::Write), (self.execute, Access::Execute),
+        ].iter().filter(|(set, _)| *set).map(|&(_, access)| access).collect();
+        if accesses.is_empty() {
+            return Err("watch reads, writes or execution".to_owned());
+        }
+        Ok(Watchpoint::new(start, end, &accesses))
+    }
+}
+
+fn debug_window_watchpoints(
+    ui: &mut Ui,
+    chip8_state: &mut Chip8State,
+    watchpoint_input: &mut WatchpointInput,
+    args: &Args
+) {
+    ui.tree_node(hash!(), "Watchpoints", |ui| {
+        ui.label(None, "From address or label: ");
+        ui.input_text(hash!(), "< --", &mut watchpoint_input.start);
+        ui.label(None, "To address or label: ");
+        ui.input_text(hash!(), "< --", &mut watchpoint_input.end);
+        ui.checkbox(hash!(), "Read", &mut watchpoint_input.read);
+        ui.checkbox(hash!(), "Write", &mut watchpoint_input.write);
+        ui.checkbox(hash!(), "Execute", &mut watchpoint_input.execute);
+        ui.separator();
+        if ui.button(None, "Add watchpoint") {
+            match watchpoint_input.to_watchpoint(args) {
+                Ok(watchpoint) => chip8_state.watchpoints.push(watchpoint),
+                Err(e) => eprintln!("Invalid watchpoint: {}", e),
+            }
+        }
+
+        ui.separator();
+        ui.label(None, "Watchpoints: ");
+        for i in 0..chip8_state.watchpoints.len() {
+            ui.label(None, &format!("{i:2}: {}", chip8_state.watchpoints[i].describe()));
+            let toggle = if chip8_state.watchpoints[i].enabled { "Disable" } else { "Enable" };
+            if ui.button(None, toggle) {
+                chip8_state.watchpoints[i].enabled = !chip8_state.watchpoints[i].enabled;
+            }
+            ui.same_line(0.0);
+            if ui.button(None, "Remove") {
+                chip8_state.watchpoints.remove(i);
                 // Break to avoid mismatched lengths after removing
                 break;
             }
@@ -123,10 +273,21 @@ fn debug_window_speedhacks(
     });
 }
 
+/// Contents of the debug windows that are kept between frames
+#[derive(Default)]
+pub struct DebugInput {
+    pub steps: String,
+    pub breakpoint: BreakpointInput,
+    pub watchpoint: WatchpointInput,
+    pub multiplier: String,
+    pub snapshots: Vec<Chip8State>,
+}
+
 fn debug_window_snapshots(
     ui: &mut Ui,
     chip8_state: &mut Chip8State,
     snapshots: &mut Vec<Chip8State>,
+    movie: bool,
 ) {
     ui.tree_node(hash!(), "Snapshots", |ui| {
         if ui.button(None, "Add snapshot") {
@@ -143,37 +304,40 @@ fn debug_window_snapshots(
                 // Break to avoid mismatched lengths after removing
                 break;
             }
-            ui.same_line(0.0);
-            if ui.button(None, "Load") {
-                *chip8_state = snapshots[i].clone();
+            if !movie {
+                ui.same_line(0.0);
+                if ui.button(None, "Load") {
+                    *chip8_state = snapshots[i].clone();
+                }
             }
             ui.separator();
         }
     });
 }
 
-fn debug_window(
-    chip8_state: &mut Chip8State,
-    snapshots: &mut Vec<Chip8State>,
-    steps: &mut String,
-    breakpoint_addr: &mut String,
-    multiplier: &mut String,
-    args: &Args
-) {
+fn debug_window(chip8_state: &mut Chip8State, input: &mut DebugInput, movie: bool, args: &Args) {
     widgets::Window::new(hash!(), vec2(0., 50.), vec2(200., 300.))
         .label("Debug")
         .ui(&mut root_ui(), |ui| {
-            debug_window_execution(ui, chip8_state, steps, args);
-            debug_window_breakpoints(ui, chip8_state, breakpoint_addr);
-            debug_window_speedhacks(ui, chip8_state, multiplier);
-            debug_window_snapshots(ui, chip8_state, snapshots);
+            debug_window_execution(ui, chip8_state, &mut input.steps, movie, args);
+            debug_window_breakpoints(ui, chip8_state, &mut input.breakpoint, args);
+            debug_window_watchpoints(ui, chip8_state, &mut input.watchpoint, args);
+            debug_window_speedhacks(ui, chip8_state, &mut input.multiplier);
+            debug_window_snapshots(ui, chip8_state, &mut input.snapshots, movie);
         });
 }
 
-fn registers_window(chip8_state: &Chip8State) {
+fn registers_window(chip8_state: &Chip8State, args: &Args) {
     widgets::Window::new(hash!(), vec2(300., 50.), vec2(250., 300.))
         .label("State")
         .ui(&mut root_ui(), |ui| {
+            match &args.database_entry {
+                Some(entry) => ui.label(None, &format!("database: {}", entry)),
+                None => ui.label(None, "database: no match"),
+            }
+            if let Some(watch_hit) = &chip8_state.watch_hit {
+                print_ui_text(ui, watch_hit.to_string());
+            }
             print_ui_text(ui, chip8_state.get_state_string());
         });
 }
@@ -186,15 +350,10 @@ fn disassembly_window(chip8_state: &Chip8State) {
         });
 }
 
-pub fn debug_windows(
-    chip8_state: &mut Chip8State,
-    snapshots: &mut Vec<Chip8State>,
-    steps: &mut String,
-    breakpoint_addr: &mut String,
-    multiplier: &mut String,
-    args: &Args
-) {
-    debug_window(chip8_state, snapshots, steps, breakpoint_addr, multiplier, args);
-    registers_window(chip8_state);
+/// Draw the debug windows. While a movie is recorded or played
+/// everything that moves the state around in time is left out
+pub fn debug_windows(chip8_state: &mut Chip8State, input: &mut DebugInput, movie: bool, args: &Args) {
+    debug_window(chip8_state, input, movie, args);
+    registers_window(chip8_state, args);
     disassembly_window(chip8_state);
 }
@@ -1,581 +1,325 @@
 use core::fmt;
-
+use crate::state::{Platform, QuirksConfig};
+
+/// A decoded instruction with its operands. Shared by the interpreter
+/// and the disassembler, so new opcodes only have to be added here.
+/// Decoded based on the opcode tables from:
+/// https://en.wikipedia.org/wiki/CHIP-8#Opcode_table
+/// https://johnearnest.github.io/Octo/docs/SuperChip.html
+/// https://johnearnest.github.io/Octo/docs/XO-ChipSpecification.html
 #[derive(Debug)]
-#[derive(Clone, Copy)]
-pub enum InstructionType {
-    CallRca1802CodeRoutine,
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    // 0NNN
+    CallRca1802CodeRoutine { nnn: u16 },
+    // 00E0
     ClearDisplay,
+    // 00EE
     ReturnFromSubroutine,
-    Jmp,
-    Call,
-    SkipEq,
-    SkipNeq,
-    SkipRegsEq,
-    SetVal,
-    AddVal,
-    SetReg,
-    OrReg,
-    AndReg,
-    XorReg,
-    AddReg,
-    SubReg,
-    RshReg,
-    ReverseSubReg,
-    LshReg,
-    SkipRegsNeq,
-    SetAddr,
-    JmpPlus,
-    Rand,
-    Draw,
-    SkipIfPressed,
-    SkipIfNotPressed,
-    GetDelayTimer,
-    GetKeypress,
-    SetDelayTimer,
-    SetSoundTimer,
-    AddToAddr,
-    SetAddrToSpriteAddr,
-    StoreBcd,
-    RegDump,
-    RegLoad,
-    BadInstruction,
-}
-
-impl InstructionType {
-    fn get_string(instruction_type: InstructionType) ->  &'static str {
-        match instruction_type {
-            Self::CallRca1802CodeRoutine => "call_rca1802_code_routine",
-            Self::ClearDisplay => "clear_display",
-            Self::ReturnFromSubroutine => "return",
-            Self::Jmp => "jmp",
-            Self::Call => "call",
-            Self::SkipEq => "skip_eq",
-            Self::SkipNeq => "skip_neq",
-            Self::SkipRegsEq => "skip_eq",
-            Self::SetVal => "set",
-            Self::AddVal => "add",
-            Self::SubReg => "sub",
-            Self::SetReg => "set",
-            Self::OrReg => "or",
-            Self::AndReg => "and",
-            Self::XorReg => "xor",
-            Self::AddReg => "add",
-            Self::RshReg => "rsh",
-            Self::ReverseSubReg => "reverse_sub",
-            Self::LshReg => "lsh",
-            Self::SkipRegsNeq => "skip_neq",
-            Self::SetAddr => "set",
-            Self::JmpPlus => "jmp",
-            Self::Rand => "rand",
-            Self::Draw => "draw",
-            Self::SkipIfPressed => "skip_if_pressed",
-            Self::SkipIfNotPressed => "skip_if_not_pressed",
-            Self::GetDelayTimer => "get_delay_timer",
-            Self::GetKeypress => "get_keypress",
-            Self::SetDelayTimer => "set_delay_timer",
-            Self::SetSoundTimer => "set_sound_timer",
-            Self::AddToAddr => "add",
-            Self::SetAddrToSpriteAddr => "set_addr_to_sprite_addr",
-            Self::StoreBcd => "store_bcd",
-            Self::RegDump => "reg_dump",
-            Self::RegLoad => "reg_load",
-            Self::BadInstruction => "bad_instruction",
-        }
-    }
-}
-
-#[derive(Debug)]
-pub struct Instruction {
-    pub instruction_type: InstructionType,
-    pub args: String,
+    // 00CN
+    ScrollDown { n: u8 },
+    // 00DN
+    ScrollUp { n: u8 },
+    // 00FB
+    ScrollRight,
+    // 00FC
+    ScrollLeft,
+    // 00FD
+    Exit,
+    // 00FE
+    Lores,
+    // 00FF
+    Hires,
+    // 1NNN
+    Jmp { nnn: u16 },
+    // 2NNN
+    Call { nnn: u16 },
+    // 3XNN
+    SkipEq { x: usize, nn: u8 },
+    // 4XNN
+    SkipNeq { x: usize, nn: u8 },
+    // 5XY0
+    SkipRegsEq { x: usize, y: usize },
+    // 5XY2
+    SaveRegsRange { x: usize, y: usize },
+    // 5XY3
+    LoadRegsRange { x: usize, y: usize },
+    // 6XNN
+    SetVal { x: usize, nn: u8 },
+    // 7XNN
+    AddVal { x: usize, nn: u8 },
+    // 8XY0
+    SetReg { x: usize, y: usize },
+    // 8XY1
+    OrReg { x: usize, y: usize },
+    // 8XY2
+    AndReg { x: usize, y: usize },
+    // 8XY3
+    XorReg { x: usize, y: usize },
+    // 8XY4
+    AddReg { x: usize, y: usize },
+    // 8XY5
+    SubReg { x: usize, y: usize },
+    // 8XY6
+    RshReg { x: usize, y: usize },
+    // 8XY7
+    ReverseSubReg { x: usize, y: usize },
+    // 8XYE
+    LshReg { x: usize, y: usize },
+    // 9XY0
+    SkipRegsNeq { x: usize, y: usize },
+    // ANNN
+    SetAddr { nnn: u16 },
+    // F000 NNNN
+    LongSetAddr { nnnn: u16 },
+    // BNNN
+    JmpPlus { nnn: u16 },
+    // BXNN, the jumping quirk
+    JmpPlusX { x: usize, nnn: u16 },
+    // CXNN
+    Rand { x: usize, nn: u8 },
+    // DXYN
+    Draw { x: usize, y: usize, n: u8 },
+    // EX9E
+    SkipIfPressed { x: usize },
+    // EXA1
+    SkipIfNotPressed { x: usize },
+    // FN01
+    SelectPlanes { n: u8 },
+    // F002
+    LoadAudioPattern,
+    // FX07
+    GetDelayTimer { x: usize },
+    // FX0A
+    GetKeypress { x: usize },
+    // FX15
+    SetDelayTimer { x: usize },
+    // FX18
+    SetSoundTimer { x: usize },
+    // FX1E
+    AddToAddr { x: usize },
+    // FX29
+    SetAddrToSpriteAddr { x: usize },
+    // FX30
+    SetAddrToBigSpriteAddr { x: usize },
+    // FX33
+    StoreBcd { x: usize },
+    // FX3A
+    SetPitch { x: usize },
+    // FX55
+    RegDump { x: usize },
+    // FX65
+    RegLoad { x: usize },
+    // FX75
+    SaveRplFlags { x: usize },
+    // FX85
+    LoadRplFlags { x: usize },
+    // everything else
+    Unknown { opcode: u16 },
 }
 
 impl Instruction {
-    pub fn from(inst: u16) -> Instruction {
-        find_instruction_func(inst)(inst)
-    }
-}
-
-impl fmt::Display for Instruction {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{} {}",
-                 InstructionType::get_string(self.instruction_type),
-                 self.args,
-        )?;
-        Ok(())
-    }
-}
-
-fn find_instruction_func(inst: u16) -> fn(u16) -> Instruction {
-    if inst == 0x00e0 {
-        clear_display
-    } else if inst == 0x00ee {
-        return_from_subroutine
-    } else if inst & 0xf000 == 0x0000 {
-        call_rca1802_code_routine
-    } else if inst & 0xf000 == 0x1000 {
-        jmp
-    } else if inst & 0xf000 == 0x2000 {
-        call
-    } else if inst & 0xf000 == 0x3000 {
-        skip_eq
-    } else if inst & 0xf000 == 0x4000 {
-        skip_neq
-    } else if inst & 0xf000 == 0x5000 && inst & 0x000f == 0x0 {
-        skip_regs_eq
-    } else if inst & 0xf000 == 0x6000 {
-        set_val
-    } else if inst & 0xf000 == 0x7000 {
-        add_val
-    } else if inst & 0xf000 == 0x8000 && inst & 0x000f == 0x0 {
-        set_reg
-    } else if inst & 0xf000 == 0x8000 && inst & 0x000f == 0x1 {
-        or_reg
-    } else if inst & 0xf000 == 0x8000 && inst & 0x000f == 0x2 {
-        and_reg
-    } else if inst & 0xf000 == 0x8000 && inst & 0x000f == 0x3 {
-        xor_reg
-    } else if inst & 0xf000 == 0x8000 && inst & 0x000f == 0x4 {
-        add_reg
-    } else if inst & 0xf000 == 0x8000 && inst & 0x000f == 0x5 {
-        sub_reg
-    } else if inst & 0xf000 == 0x8000 && inst & 0x000f == 0x6 {
-        rsh_reg
-    } else if inst & 0xf000 == 0x8000 && inst & 0x000f == 0x7 {
-        reverse_sub_reg
-    } else if inst & 0xf000 == 0x8000 && inst & 0x000f == 0xe {
-        lsh_reg
-    } else if inst & 0xf000 == 0x9000 && inst & 0x000f == 0x0 {
-        skip_regs_neq
-    } else if inst & 0xf000 == 0xa000 {
-        set_addr
-    } else if inst & 0xf000 == 0xb000 {
-        jmp_plus
-    } else if inst & 0xf000 == 0xc000 {
-        rand
-    } else if inst & 0xf000 == 0xd000 {
-        draw
-    } else if inst & 0xf000 == 0xe000 && inst & 0x00ff == 0xe9 {
-        skip_if_pressed
-    } else if inst & 0xf000 == 0xe000 && inst & 0x00ff == 0xa1 {
-        skip_if_not_pressed
-    } else if inst & 0xf000 == 0xf000 && inst & 0x00ff == 0x07 {
-        get_delay_timer
-    } else if inst & 0xf000 == 0xf000 && inst & 0x00ff == 0x0a {
-        get_keypress
-    } else if inst & 0xf000 == 0xf000 && inst & 0x00ff == 0x15 {
-        set_delay_timer
-    } else if inst & 0xf000 == 0xf000 && inst & 0x00ff == 0x18 {
-        set_sound_timer
-    } else if inst & 0xf000 == 0xf000 && inst & 0x00ff == 0x1e {
-        add_to_addr
-    } else if inst & 0xf000 == 0xf000 && inst & 0x00ff == 0x29 {
-        set_addr_to_sprite_addr
-    } else if inst & 0xf000 == 0xf000 && inst & 0x00ff == 0x33 {
-        store_bcd
-    } else if inst & 0xf000 == 0xf000 && inst & 0x00ff == 0x55 {
-        reg_dump
-    } else if inst & 0xf000 == 0xf000 && inst & 0x00ff == 0x65 {
-        reg_load
-    } else {
-        bad_instruction
-    }
-}
-
-// Below you can see functions corresponding to the various
-// instructions. Made based on the opcode table from:
-// https://en.wikipedia.org/wiki/CHIP-8#Opcode_table
-
-// 0NNN
-fn call_rca1802_code_routine(inst: u16) -> Instruction {
-    assert_eq!(inst & 0xf000, 0x0000);
-    Instruction {
-        instruction_type: InstructionType::CallRca1802CodeRoutine,
-        args: String::from(""),
-    }
-}
-
-// 00E0
-fn clear_display(inst: u16) -> Instruction {
-    assert_eq!(inst, 0x00e0);
-    Instruction {
-        instruction_type: InstructionType::ClearDisplay,
-        args: String::from(""),
-    }
-}
-
-// 00ee
-fn return_from_subroutine(inst: u16) -> Instruction {
-    assert_eq!(inst, 0x00ee);
-    Instruction {
-        instruction_type: InstructionType::ReturnFromSubroutine,
-        args: String::from(""),
-    }
-}
-
-// 1NNN
-fn jmp(inst: u16) -> Instruction {
-    assert_eq!((inst & 0xf000) >> 12, 1);
-    let nnn = inst & 0x0fff;
-    Instruction {
-        instruction_type: InstructionType::Jmp,
-        args: format!("{:04x}", nnn),
-    }
-}
-
-// 2NNN
-fn call(inst: u16) -> Instruction {
-    assert_eq!((inst & 0xf000) >> 12, 2);
-    let nnn = inst & 0x0fff;
-    Instruction {
-        instruction_type: InstructionType::Call,
-        args: format!("{:04x}", nnn),
-    }
-}
-
-// 3XNN
-fn skip_eq(inst: u16) -> Instruction {
-    assert_eq!((inst & 0xf000) >> 12, 3);
-    let nn = (inst & 0x00ff) as u8;
-    let x = ((inst & 0x0f00) >> 8) as usize;
-    Instruction {
-        instruction_type: InstructionType::SkipEq,
-        args: format!("reg[{}] {:02x}", x, nn),
-    }
-}
-
-// 4XNN
-fn skip_neq(inst: u16) -> Instruction {
-    assert_eq!((inst & 0xf000) >> 12, 4);
-    let nn = (inst & 0x00ff) as u8;
-    let x = ((inst & 0x0f00) >> 8) as usize;
-    Instruction {
-        instruction_type: InstructionType::SkipNeq,
-        args: format!("reg[{}] {:02x}", x, nn),
-    }
-}
-
-// 5XY0
-fn skip_regs_eq(inst: u16) -> Instruction {
-    assert_eq!((inst & 0xf000) >> 12, 5);
-    assert_eq!(inst & 0x000f, 0);
-    let x = ((inst & 0x0f00) >> 8) as usize;
-    let y = ((inst & 0x00f0) >> 4) as usize;
-    Instruction {
-        instruction_type: InstructionType::SkipRegsEq,
-        args: format!("reg[{}] reg[{}]", x, y),
-    }
-}
-
-// 6XNN
-fn set_val(inst: u16) -> Instruction {
-    assert_eq!((inst & 0xf000) >> 12, 6);
-    let x = ((inst & 0x0f00) >> 8) as usize;
-    let nn: u8 = (inst & 0x00ff) as u8;
-    Instruction {
-        instruction_type: InstructionType::SetVal,
-        args: format!("reg[{}] {:02x}", x, nn),
-    }
-}
-
-// 7XNN
-fn add_val(inst: u16) -> Instruction {
-    assert_eq!((inst & 0xf000) >> 12, 7);
-    let x = ((inst & 0x0f00) >> 8) as usize;
-    let nn: u8 = (inst & 0x00ff) as u8;
-    Instruction {
-        instruction_type: InstructionType::AddVal,
-        args: format!("reg[{}] {:02x}", x, nn),
-    }
-}
-
-// 8XY0
-fn set_reg(inst: u16) -> Instruction {
-    assert_eq!((inst & 0xf000) >> 12, 8);
-    assert_eq!(inst & 0x000f, 0);
-    let x = ((inst & 0x0f00) >> 8) as usize;
-    let y = ((inst & 0x00f0) >> 4) as usize;
-    Instruction {
-        instruction_type: InstructionType::SetReg,
-        args: format!("reg[{}] reg[{}]", x, y),
-    }
-}
-
-// 8XY1
-fn or_reg(inst: u16) -> Instruction {
-    assert_eq!((inst & 0xf000) >> 12, 8);
-    assert_eq!(inst & 0x000f, 1);
-    let x = ((inst & 0x0f00) >> 8) as usize;
-    let y = ((inst & 0x00f0) >> 4) as usize;
-    Instruction {
-        instruction_type: InstructionType::OrReg,
-        args: format!("reg[{}] reg[{}]", x, y),
-    }
-}
-
-// 8XY2
-fn and_reg(inst: u16) -> Instruction {
-    assert_eq!((inst & 0xf000) >> 12, 8);
-    assert_eq!(inst & 0x000f, 2);
-    let x = ((inst & 0x0f00) >> 8) as usize;
-    let y = ((inst & 0x00f0) >> 4) as usize;
-    Instruction {
-        instruction_type: InstructionType::AndReg,
-        args: format!("reg[{}] reg[{}]", x, y),
-    }
-}
-
-// 8XY3
-fn xor_reg(inst: u16) -> Instruction {
-    assert_eq!((inst & 0xf000) >> 12, 8);
-    assert_eq!(inst & 0x000f, 3);
-    let x = ((inst & 0x0f00) >> 8) as usize;
-    let y = ((inst & 0x00f0) >> 4) as usize;
-    Instruction {
-        instruction_type: InstructionType::XorReg,
-        args: format!("reg[{}] reg[{}]", x, y),
-    }
-}
-
-// 8XY4
-fn add_reg(inst: u16) -> Instruction {
-    assert_eq!((inst & 0xf000) >> 12, 8);
-    assert_eq!(inst & 0x000f, 4);
-    let x = ((inst & 0x0f00) >> 8) as usize;
-    let y = ((inst & 0x00f0) >> 4) as usize;
-    Instruction {
-        instruction_type: InstructionType::AddReg,
-        args: format!("reg[{}] reg[{}]", x, y),
-    }
-}
-
-// 8XY5
-fn sub_reg(inst: u16) -> Instruction {
-    assert_eq!((inst & 0xf000) >> 12, 8);
-    assert_eq!(inst & 0x000f, 5);
-    let x = ((inst & 0x0f00) >> 8) as usize;
-    let y = ((inst & 0x00f0) >> 4) as usize;
-    Instruction {
-        instruction_type: InstructionType::SubReg,
-        args: format!("reg[{}] reg[{}]", x, y),
-    }
-}
-
-// 8XY6
-fn rsh_reg(inst: u16) -> Instruction {
-    assert_eq!((inst & 0xf000) >> 12, 8);
-    assert_eq!(inst & 0x000f, 6);
-    let x = ((inst & 0x0f00) >> 8) as usize;
-    Instruction {
-        instruction_type: InstructionType::RshReg,
-        args: format!("reg[{}]", x),
-    }
-}
-
-// 8XY7
-fn reverse_sub_reg(inst: u16) -> Instruction {
-    assert_eq!((inst & 0xf000) >> 12, 8);
-    assert_eq!(inst & 0x000f, 7);
-    let x = ((inst & 0x0f00) >> 8) as usize;
-    let y = ((inst & 0x00f0) >> 4) as usize;
-    Instruction {
-        instruction_type: InstructionType::ReverseSubReg,
-        args: format!("reg[{}] reg[{}]", x, y),
-    }
-}
-
-// 8XYE
-fn lsh_reg(inst: u16) -> Instruction {
-    assert_eq!((inst & 0xf000) >> 12, 8);
-    assert_eq!(inst & 0x000f, 0xe);
-    let x = ((inst & 0x0f00) >> 8) as usize;
-    Instruction {
-        instruction_type: InstructionType::LshReg,
-        args: format!("reg[{}]", x),
-    }
-}
-
-// 9XY0
-fn skip_regs_neq(inst: u16) -> Instruction {
-    assert_eq!((inst & 0xf000) >> 12, 9);
-    assert_eq!(inst & 0x000f, 0);
-    let x = ((inst & 0x0f00) >> 8) as usize;
-    let y = ((inst & 0x00f0) >> 4) as usize;
-    Instruction {
-        instruction_type: InstructionType::SkipRegsEq,
-        args: format!("reg[{}] reg[{}]", x, y),
-    }
-}
-
-// ANNN
-fn set_addr(inst: u16) -> Instruction {
-    assert_eq!((inst & 0xf000) >> 12, 0xa);
-    let nnn = inst & 0x0fff;
-    Instruction {
-        instruction_type: InstructionType::SetAddr,
-        args: format!("I {:04x}", nnn),
-    }
-}
-
-// BNNN
-fn jmp_plus(inst: u16) -> Instruction {
-    assert_eq!((inst & 0xf000) >> 12, 0xb);
-    let nnn = inst & 0x0fff;
-    Instruction {
-        instruction_type: InstructionType::JmpPlus,
-        args: format!("{:04x} + reg[0]", nnn),
-    }
-}
-
-// CXNN
-fn rand(inst: u16) -> Instruction {
-    assert_eq!((inst & 0xf000) >> 12, 0xc);
-    let x = ((inst & 0x0f00) >> 8) as usize;
-    let nn: u8 = (inst & 0xff) as u8;
-    Instruction {
-        instruction_type: InstructionType::Rand,
-        args: format!("reg[{}] {:02x}", x, nn),
-    }
-}
-
-// DXYN
-fn draw(inst: u16) -> Instruction {
-    assert_eq!((inst & 0xf000) >> 12, 0xd);
-    let x = ((inst & 0x0f00) >> 8) as usize;
-    let y = ((inst & 0x00f0) >> 4) as usize;
-    let n: u8 = (inst & 0xf) as u8;
-    Instruction {
-        instruction_type: InstructionType::Draw,
-        args: format!("reg[{}] reg[{}] {:02x}", x, y, n),
-    }
-}
-
-// EX9E
-fn skip_if_pressed(inst: u16) -> Instruction {
-    assert_eq!((inst & 0xf000) >> 12, 0xe);
-    assert_eq!(inst & 0x00ff, 0x9e);
-    let x = ((inst & 0x0f00) >> 8) as usize;
-    Instruction {
-        instruction_type: InstructionType::SkipIfPressed,
-        args: format!("reg[{}]", x),
-    }
-}
-
-// EXA1
-fn skip_if_not_pressed(inst: u16) -> Instruction {
-    assert_eq!((inst & 0xf000) >> 12, 0xe);
-    assert_eq!(inst & 0x00ff, 0xa1);
-    let x = ((inst & 0x0f00) >> 8) as usize;
-    Instruction {
-        instruction_type: InstructionType::SkipIfNotPressed,
-        args: format!("reg[{}]", x),
-    }
-}
-
-// FX07
-fn get_delay_timer(inst: u16) -> Instruction {
-    assert_eq!((inst & 0xf000) >> 12, 0xf);
-    assert_eq!(inst & 0x00ff, 7);
-    let x = ((inst & 0x0f00) >> 8) as usize;
-    Instruction {
-        instruction_type: InstructionType::GetDelayTimer,
-        args: format!("reg[{}]", x),
-    }
-}
-
-// FX0A
-fn get_keypress(inst: u16) -> Instruction {
-    assert_eq!((inst & 0xf000) >> 12, 0xf);
-    assert_eq!(inst & 0x00ff, 0x0a);
-    let x = ((inst & 0x0f00) >> 8) as usize;
-    Instruction {
-        instruction_type: InstructionType::GetKeypress,
-        args: format!("reg[{}]", x),
-    }
-}
-
-// FX15
-fn set_delay_timer(inst: u16) -> Instruction {
-    assert_eq!((inst & 0xf000) >> 12, 0xf);
-    assert_eq!(inst & 0x00ff, 0x15);
-    let x = ((inst & 0x0f00) >> 8) as usize;
-    Instruction {
-        instruction_type: InstructionType::GetDelayTimer,
-        args: format!("reg[{}]", x),
-    }
-}
-
-// FX18
-fn set_sound_timer(inst: u16) -> Instruction {
-    assert_eq!((inst & 0xf000) >> 12, 0xf);
-    assert_eq!(inst & 0x00ff, 0x18);
-    let x = ((inst & 0x0f00) >> 8) as usize;
-    Instruction {
-        instruction_type: InstructionType::SetSoundTimer,
-        args: format!("reg[{}]", x),
-    }
-}
-
-// FX1E
-fn add_to_addr(inst: u16) -> Instruction {
-    assert_eq!((inst & 0xf000) >> 12, 0xf);
-    assert_eq!(inst & 0x00ff, 0x1e);
-    let x = ((inst & 0x0f00) >> 8) as usize;
-    Instruction {
-        instruction_type: InstructionType::AddToAddr,
-        args: format!("reg[{}]", x),
+    /// Size in bytes of the instruction starting with the given
+    /// opcode. Needed before decoding, since the XO-CHIP F000 NNNN
+    /// instruction takes four bytes
+    pub fn size_from_opcode(opcode: u16, quirks_config: &QuirksConfig) -> usize {
+        if opcode == 0xf000 && quirks_config.platform == Platform::XoChip {
+            4
+        } else {
+            2
+        }
     }
-}
 
-// FX29
-fn set_addr_to_sprite_addr(inst: u16) -> Instruction {
-    // TODO
-    assert_eq!((inst & 0xf000) >> 12, 0xf);
-    assert_eq!(inst & 0x00ff, 0x29);
-    let x = ((inst & 0x0f00) >> 8) as usize;
-    Instruction {
-        instruction_type: InstructionType::SetAddrToSpriteAddr,
-        args: format!("reg[{}]", x),
+    /// Decode an instruction from the beginning of a byte slice. Which
+    /// instructions exist depends on the platform and the quirks
+    pub fn decode(bytes: &[u8], quirks_config: &QuirksConfig) -> Instruction {
+        let opcode = u16::from_be_bytes([bytes[0], bytes[1]]);
+        let x = ((opcode & 0x0f00) >> 8) as usize;
+        let y = ((opcode & 0x00f0) >> 4) as usize;
+        let n = (opcode & 0x000f) as u8;
+        let nn = (opcode & 0x00ff) as u8;
+        let nnn = opcode & 0x0fff;
+        let super_chip = quirks_config.platform != Platform::Chip8;
+        let xo_chip = quirks_config.platform == Platform::XoChip;
+
+        match opcode >> 12 {
+            0x0 => match nnn {
+                0x0e0 => Self::ClearDisplay,
+                0x0ee => Self::ReturnFromSubroutine,
+                0x0c0..=0x0cf if super_chip => Self::ScrollDown { n },
+                0x0d0..=0x0df if xo_chip => Self::ScrollUp { n },
+                0x0fb if super_chip => Self::ScrollRight,
+                0x0fc if super_chip => Self::ScrollLeft,
+                0x0fd if super_chip => Self::Exit,
+                0x0fe if super_chip => Self::Lores,
+                0x0ff if super_chip => Self::Hires,
+                _ => Self::CallRca1802CodeRoutine { nnn },
+            },
+            0x1 => Self::Jmp { nnn },
+            0x2 => Self::Call { nnn },
+            0x3 => Self::SkipEq { x, nn },
+            0x4 => Self::SkipNeq { x, nn },
+            0x5 => match n {
+                0x0 => Self::SkipRegsEq { x, y },
+                0x2 if xo_chip => Self::SaveRegsRange { x, y },
+                0x3 if xo_chip => Self::LoadRegsRange { x, y },
+                _ => Self::Unknown { opcode },
+            },
+            0x6 => Self::SetVal { x, nn },
+            0x7 => Self::AddVal { x, nn },
+            0x8 => match n {
+                0x0 => Self::SetReg { x, y },
+                0x1 => Self::OrReg { x, y },
+                0x2 => Self::AndReg { x, y },
+                0x3 => Self::XorReg { x, y },
+                0x4 => Self::AddReg { x, y },
+                0x5 => Self::SubReg { x, y },
+                0x6 => Self::RshReg { x, y },
+                0x7 => Self::ReverseSubReg { x, y },
+                0xe => Self::LshReg { x, y },
+                _ => Self::Unknown { opcode },
+            },
+            0x9 if n == 0 => Self::SkipRegsNeq { x, y },
+            0xa => Self::SetAddr { nnn },
+            // https://tobiasvl.github.io/blog/write-a-chip-8-emulator/#bnnn-jump-with-offset
+            0xb if quirks_config.jumping => Self::JmpPlusX { x, nnn },
+            0xb => Self::JmpPlus { nnn },
+            0xc => Self::Rand { x, nn },
+            0xd => Self::Draw { x, y, n },
+            0xe => match nn {
+                0x9e => Self::SkipIfPressed { x },
+                0xa1 => Self::SkipIfNotPressed { x },
+                _ => Self::Unknown { opcode },
+            },
+            0xf => match nn {
+                0x00 if x == 0 && xo_chip && bytes.len() >= 4 => {
+                    Self::LongSetAddr { nnnn: u16::from_be_bytes([bytes[2], bytes[3]]) }
+                },
+                0x01 if xo_chip => Self::SelectPlanes { n: x as u8 },
+                0x02 if x == 0 && xo_chip => Self::LoadAudioPattern,
+                0x07 => Self::GetDelayTimer { x },
+                0x0a => Self::GetKeypress { x },
+                0x15 => Self::SetDelayTimer { x },
+                0x18 => Self::SetSoundTimer { x },
+                0x1e => Self::AddToAddr { x },
+                0x29 => Self::SetAddrToSpriteAddr { x },
+                0x30 if super_chip => Self::SetAddrToBigSpriteAddr { x },
+                0x33 => Self::StoreBcd { x },
+                0x3a if xo_chip => Self::SetPitch { x },
+                0x55 => Self::RegDump { x },
+                0x65 => Self::RegLoad { x },
+                0x75 if super_chip => Self::SaveRplFlags { x },
+                0x85 if super_chip => Self::LoadRplFlags { x },
+                _ => Self::Unknown { opcode },
+            },
+            _ => Self::Unknown { opcode },
+        }
     }
-}
 
-// FX33
-fn store_bcd(inst: u16) -> Instruction {
-    assert_eq!((inst & 0xf000) >> 12, 0xf);
-    assert_eq!(inst & 0x00ff, 0x33);
-    let x = ((inst & 0x0f00) >> 8) as usize;
-    Instruction {
-        instruction_type: InstructionType::StoreBcd,
-        args: format!("reg[{}]", x),
+    /// Size of the instruction in bytes
+    pub fn size(&self) -> usize {
+        match self {
+            Self::LongSetAddr { .. } => 4,
+            _ => 2,
+        }
     }
-}
 
-// FX55
-fn reg_dump(inst: u16) -> Instruction {
-    assert_eq!((inst & 0xf000) >> 12, 0xf);
-    assert_eq!(inst & 0x00ff, 0x55);
-    let x = ((inst & 0x0f00) >> 8) as usize;
-    Instruction {
-        instruction_type: InstructionType::RegDump,
-        args: format!("reg[{}]", x),
-    }
-}
-
-// FX65
-fn reg_load(inst: u16) -> Instruction {
-    assert_eq!((inst & 0xf000) >> 12, 0xf);
-    assert_eq!(inst & 0x00ff, 0x65);
-    let x = ((inst & 0x0f00) >> 8) as usize;
-    Instruction {
-        instruction_type: InstructionType::RegLoad,
-        args: format!("reg[{}]", x),
+    fn name(&self) -> &'static str {
+        match self {
+            Self::CallRca1802CodeRoutine { .. } => "call_rca1802_code_routine",
+            Self::ClearDisplay => "clear_display",
+            Self::ReturnFromSubroutine => "return",
+            Self::ScrollDown { .. } => "scroll_down",
+            Self::ScrollUp { .. } => "scroll_up",
+            Self::ScrollRight => "scroll_right",
+            Self::ScrollLeft => "scroll_left",
+            Self::Exit => "exit",
+            Self::Lores => "lores",
+            Self::Hires => "hires",
+            Self::Jmp { .. } => "jmp",
+            Self::Call { .. } => "call",
+            Self::SkipEq { .. } => "skip_eq",
+            Self::SkipNeq { .. } => "skip_neq",
+            Self::SkipRegsEq { .. } => "skip_eq",
+            Self::SaveRegsRange { .. } => "save_range",
+            Self::LoadRegsRange { .. } => "load_range",
+            Self::SetVal { .. } => "set",
+            Self::AddVal { .. } => "add",
+            Self::SetReg { .. } => "set",
+            Self::OrReg { .. } => "or",
+            Self::AndReg { .. } => "and",
+            Self::XorReg { .. } => "xor",
+            Self::AddReg { .. } => "add",
+            Self::SubReg { .. } => "sub",
+            Self::RshReg { .. } => "rsh",
+            Self::ReverseSubReg { .. } => "reverse_sub",
+            Self::LshReg { .. } => "lsh",
+            Self::SkipRegsNeq { .. } => "skip_neq",
+            Self::SetAddr { .. } => "set",
+            Self::LongSetAddr { .. } => "set",
+            Self::JmpPlus { .. } => "jmp",
+            Self::JmpPlusX { .. } => "jmp",
+            Self::Rand { .. } => "rand",
+            Self::Draw { .. } => "draw",
+            Self::SkipIfPressed { .. } => "skip_if_pressed",
+            Self::SkipIfNotPressed { .. } => "skip_if_not_pressed",
+            Self::SelectPlanes { .. } => "select_planes",
+            Self::LoadAudioPattern => "load_audio_pattern",
+            Self::GetDelayTimer { .. } => "get_delay_timer",
+            Self::GetKeypress { .. } => "get_keypress",
+            Self::SetDelayTimer { .. } => "set_delay_timer",
+            Self::SetSoundTimer { .. } => "set_sound_timer",
+            Self::AddToAddr { .. } => "add",
+            Self::SetAddrToSpriteAddr { .. } => "set_addr_to_sprite_addr",
+            Self::SetAddrToBigSpriteAddr { .. } => "set_addr_to_big_sprite_addr",
+            Self::StoreBcd { .. } => "store_bcd",
+            Self::SetPitch { .. } => "set_pitch",
+            Self::RegDump { .. } => "reg_dump",
+            Self::RegLoad { .. } => "reg_load",
+            Self::SaveRplFlags { .. } => "save_rpl_flags",
+            Self::LoadRplFlags { .. } => "load_rpl_flags",
+            Self::Unknown { .. } => "bad_instruction",
+        }
     }
 }
 
-// everything else
-fn bad_instruction(_inst: u16) -> Instruction {
-    Instruction {
-        instruction_type: InstructionType::BadInstruction,
-        args: String::new(),
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())?;
+        match *self {
+            Self::ClearDisplay | Self::ReturnFromSubroutine | Self::ScrollRight
+                | Self::ScrollLeft | Self::Exit | Self::Lores | Self::Hires
+                | Self::LoadAudioPattern | Self::CallRca1802CodeRoutine { .. }
+                | Self::Unknown { .. } => Ok(()),
+            Self::ScrollDown { n } | Self::ScrollUp { n } => write!(f, " {:02x}", n),
+            Self::SelectPlanes { n } => write!(f, " {:x}", n),
+            Self::Jmp { nnn } | Self::Call { nnn } => write!(f, " {:04x}", nnn),
+            Self::SetAddr { nnn } => write!(f, " I {:04x}", nnn),
+            Self::LongSetAddr { nnnn } => write!(f, " I {:04x}", nnnn),
+            Self::JmpPlus { nnn } => write!(f, " {:04x} + reg[0]", nnn),
+            Self::JmpPlusX { x, nnn } => write!(f, " {:04x} + reg[{}]", nnn, x),
+            Self::SkipEq { x, nn } | Self::SkipNeq { x, nn } | Self::SetVal { x, nn }
+                | Self::AddVal { x, nn } | Self::Rand { x, nn } => {
+                write!(f, " reg[{}] {:02x}", x, nn)
+            },
+            Self::SkipRegsEq { x, y } | Self::SaveRegsRange { x, y }
+                | Self::LoadRegsRange { x, y } | Self::SetReg { x, y } | Self::OrReg { x, y }
+                | Self::AndReg { x, y } | Self::XorReg { x, y } | Self::AddReg { x, y }
+                | Self::SubReg { x, y } | Self::ReverseSubReg { x, y }
+                | Self::SkipRegsNeq { x, y } => {
+                write!(f, " reg[{}] reg[{}]", x, y)
+            },
+            Self::RshReg { x, .. } | Self::LshReg { x, .. } => write!(f, " reg[{}]", x),
+            Self::Draw { x, y, n } => write!(f, " reg[{}] reg[{}] {:02x}", x, y, n),
+            Self::SkipIfPressed { x } | Self::SkipIfNotPressed { x }
+                | Self::GetDelayTimer { x } | Self::GetKeypress { x }
+                | Self::SetDelayTimer { x } | Self::SetSoundTimer { x }
+                | Self::AddToAddr { x } | Self::SetAddrToSpriteAddr { x }
+                | Self::SetAddrToBigSpriteAddr { x } | Self::StoreBcd { x }
+                | Self::SetPitch { x } | Self::RegDump { x } | Self::RegLoad { x }
+                | Self::SaveRplFlags { x } | Self::LoadRplFlags { x } => {
+                write!(f, " reg[{}]", x)
+            },
+        }
     }
 }
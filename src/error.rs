@@ -0,0 +1,70 @@
+use core::fmt;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// What went wrong while executing an instruction, without the
+/// information where it happened. Returned by the lower level parts
+/// of the interpreter like the stack and the memory.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Fault {
+    UnknownOpcode,
+    StackOverflow,
+    StackUnderflow,
+    MemoryOutOfBounds(usize),
+}
+
+impl Fault {
+    /// Attach the faulting instruction to the fault
+    pub fn at(self, pc: u16, opcode: u16) -> ExecutionError {
+        match self {
+            Self::UnknownOpcode => ExecutionError::UnknownOpcode { pc, opcode },
+            Self::StackOverflow => ExecutionError::StackOverflow { pc, opcode },
+            Self::StackUnderflow => ExecutionError::StackUnderflow { pc, opcode },
+            Self::MemoryOutOfBounds(addr) => {
+                ExecutionError::MemoryOutOfBounds { pc, opcode, addr }
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ExecutionError {
+    UnknownOpcode { pc: u16, opcode: u16 },
+    StackOverflow { pc: u16, opcode: u16 },
+    StackUnderflow { pc: u16, opcode: u16 },
+    MemoryOutOfBounds { pc: u16, opcode: u16, addr: usize },
+}
+
+impl fmt::Display for ExecutionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownOpcode { pc, opcode } => {
+                write!(f, "unknown opcode {:04x} at {:04x}", opcode, pc)
+            },
+            Self::StackOverflow { pc, opcode } => {
+                write!(f, "stack overflow by {:04x} at {:04x}", opcode, pc)
+            },
+            Self::StackUnderflow { pc, opcode } => {
+                write!(f, "stack underflow by {:04x} at {:04x}", opcode, pc)
+            },
+            Self::MemoryOutOfBounds { pc, opcode, addr } => {
+                write!(f, "memory access out of bounds at address {:#x} by {:04x} at {:04x}",
+                       addr, opcode, pc)
+            },
+        }
+    }
+}
+
+impl std::error::Error for ExecutionError {}
+
+/// What to do when an instruction can't be executed
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Serialize, Deserialize, Debug)]
+pub enum ErrorPolicy {
+    /// Stop the execution and report the error
+    Halt,
+    /// Skip the faulting instruction
+    Nop,
+    /// Wrap memory addresses and the stack pointer around, skip
+    /// unknown opcodes
+    Wrap,
+}
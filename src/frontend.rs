@@ -0,0 +1,43 @@
+use crate::audio::PATTERN_SIZE;
+use crate::screen::Screen;
+use crate::state::Chip8State;
+
+/// Something that can show the CHIP-8 screen
+pub trait Display {
+    fn draw(&mut self, screen: &Screen);
+}
+
+/// What should be audible right now
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Tone {
+    pub pattern: [u8; PATTERN_SIZE],
+    pub pitch: u8,
+}
+
+/// Something that can play the CHIP-8 sound
+pub trait Audio {
+    /// Called after every frame. None means silence
+    fn play(&mut self, tone: Option<Tone>);
+}
+
+/// Something that knows which keys of the hex keypad are held down
+pub trait Input {
+    fn keys(&mut self) -> [bool; 16];
+}
+
+impl Chip8State {
+    /// The sound is on for as long as the sound timer is running
+    pub fn tone(&self) -> Option<Tone> {
+        if self.sound_timer == 0 {
+            return None;
+        }
+        Some(Tone {
+            pattern: self.audio_pattern,
+            pitch: self.pitch,
+        })
+    }
+
+    pub fn poll_input(&mut self, input: &mut dyn Input) {
+        self.key_pressed = input.keys();
+    }
+}
@@ -0,0 +1,249 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::pin;
+use std::task::{Context, Poll, Waker};
+use macroquad::prelude::*;
+use macroquad::audio::{load_sound, load_sound_from_bytes, play_sound, play_sound_once, stop_sound};
+use macroquad::audio::{PlaySoundParams, Sound};
+use crate::audio::{self, Synthesizer, PATTERN_SIZE};
+use crate::frontend::{self, Tone};
+use crate::screen::{Palette, Screen, LORES_WIDTH};
+
+pub fn palette_color(color: u32) -> Color {
+    let [_, r, g, b] = color.to_be_bytes();
+    Color::from_rgba(r, g, b, 255)
+}
+
+pub struct MacroquadDisplay {
+    pub palette: Palette,
+    pub pixel_size: i32,
+}
+
+impl frontend::Display for MacroquadDisplay {
+    fn draw(&mut self, screen: &Screen) {
+        let ps = self.pixel_size as usize;
+        clear_background(palette_color(self.palette[0]));
+        let (width, height) = (screen.width(), screen.height());
+        // The window keeps the same size in both resolutions, so in the
+        // hi-res mode pixels are smaller
+        let ps = ps as f32 * LORES_WIDTH as f32 / width as f32;
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = screen.get(x, y);
+                if pixel != 0 {
+                    draw_rectangle(
+                        x as f32 * ps,
+                        y as f32 * ps,
+                        ps,
+                        ps,
+                        palette_color(self.palette[pixel as usize])
+                    );
+                }
+            }
+
+            // Draw a line on the right side of the screen to designate
+            // an end of the screen
+            draw_rectangle(
+                width as f32 * ps,
+                y as f32 * ps,
+                ps,
+                ps,
+                GRAY
+            );
+        }
+
+        // Draw a line on the bottom of the screen
+        for x in 0..=width {
+            draw_rectangle(
+                x as f32 * ps,
+                height as f32 * ps,
+                ps,
+                ps,
+                GRAY
+            );
+        }
+    }
+}
+
+/// Held down to play the emulation backwards, so it can't be a CHIP-8
+/// key
+pub const REWIND_KEY: KeyCode = KeyCode::Backspace;
+
+/// Keyboard keys that can be used in the key map, by name
+const KEY_NAMES: [(&str, KeyCode); 52] = [
+    ("A", KeyCode::A),
+    ("B", KeyCode::B),
+    ("C", KeyCode::C),
+    ("D", KeyCode::D),
+    ("E", KeyCode::E),
+    ("F", KeyCode::F),
+    ("G", KeyCode::G),
+    ("H", KeyCode::H),
+    ("I", KeyCode::I),
+    ("J", KeyCode::J),
+    ("K", KeyCode::K),
+    ("L", KeyCode::L),
+    ("M", KeyCode::M),
+    ("N", KeyCode::N),
+    ("O", KeyCode::O),
+    ("P", KeyCode::P),
+    ("Q", KeyCode::Q),
+    ("R", KeyCode::R),
+    ("S", KeyCode::S),
+    ("T", KeyCode::T),
+    ("U", KeyCode::U),
+    ("V", KeyCode::V),
+    ("W", KeyCode::W),
+    ("X", KeyCode::X),
+    ("Y", KeyCode::Y),
+    ("Z", KeyCode::Z),
+    ("0", KeyCode::Key0),
+    ("1", KeyCode::Key1),
+    ("2", KeyCode::Key2),
+    ("3", KeyCode::Key3),
+    ("4", KeyCode::Key4),
+    ("5", KeyCode::Key5),
+    ("6", KeyCode::Key6),
+    ("7", KeyCode::Key7),
+    ("8", KeyCode::Key8),
+    ("9", KeyCode::Key9),
+    ("Space", KeyCode::Space),
+    ("Enter", KeyCode::Enter),
+    ("Tab", KeyCode::Tab),
+    ("Backspace", KeyCode::Backspace),
+    ("Up", KeyCode::Up),
+    ("Down", KeyCode::Down),
+    ("Left", KeyCode::Left),
+    ("Right", KeyCode::Right),
+    ("LeftShift", KeyCode::LeftShift),
+    ("RightShift", KeyCode::RightShift),
+    ("LeftControl", KeyCode::LeftControl),
+    ("RightControl", KeyCode::RightControl),
+    ("LeftAlt", KeyCode::LeftAlt),
+    ("RightAlt", KeyCode::RightAlt),
+    ("Comma", KeyCode::Comma),
+    ("Period", KeyCode::Period),
+];
+
+pub fn parse_key_code(name: &str) -> Result<KeyCode, String> {
+    KEY_NAMES.iter()
+        .find(|(key_name, _)| key_name.eq_ignore_ascii_case(name))
+        .map(|&(_, key_code)| key_code)
+        .ok_or_else(|| format!("{} is not a known keyboard key", name))
+}
+
+pub struct MacroquadInput {
+    // Keyboard key for every CHIP-8 key
+    key_map: [KeyCode; 16],
+}
+
+impl MacroquadInput {
+    /// The default layout is the left side of a QWERTY keyboard, the
+    /// key map only has to list the keys that are different
+    pub fn new(key_map: &BTreeMap<u8, String>) -> Result<Self, String> {
+        let mut keys = [
+            KeyCode::X, KeyCode::Key1, KeyCode::Key2, KeyCode::Key3,
+            KeyCode::Q, KeyCode::W, KeyCode::E, KeyCode::A,
+            KeyCode::S, KeyCode::D, KeyCode::Z, KeyCode::C,
+            KeyCode::Key4, KeyCode::R, KeyCode::F, KeyCode::V,
+        ];
+        for (&chip8_key, keyboard_key) in key_map.iter() {
+            let key_code = parse_key_code(keyboard_key)?;
+            if key_code == REWIND_KEY {
+                return Err(format!("{} is reserved for rewinding", keyboard_key));
+            }
+            keys[chip8_key as usize] = key_code;
+        }
+        Ok(Self { key_map: keys })
+    }
+}
+
+impl frontend::Input for MacroquadInput {
+    fn keys(&mut self) -> [bool; 16] {
+        self.key_map.map(is_key_down)
+    }
+}
+
+/// Loading sounds is async only because of wasm, on the other targets
+/// the sound is ready right away, so polling the future once is enough
+fn load_sound_now(bytes: &[u8]) -> Option<Sound> {
+    let mut context = Context::from_waker(Waker::noop());
+    match pin!(load_sound_from_bytes(bytes)).poll(&mut context) {
+        Poll::Ready(Ok(sound)) => Some(sound),
+        Poll::Ready(Err(e)) => {
+            eprintln!("Error while loading the audio pattern: {}", e);
+            None
+        },
+        Poll::Pending => None,
+    }
+}
+
+/// Plays the XO-CHIP audio pattern in a loop while the sound timer
+/// is running. The sound is rendered again only when the pattern or
+/// the pitch change. Other platforms just play a beep.
+pub struct MacroquadAudio {
+    xo_chip: bool,
+    beep: Option<Sound>,
+    synthesizer: Synthesizer,
+    rendered: Option<([u8; PATTERN_SIZE], u8, Sound)>,
+    playing: bool,
+}
+
+impl MacroquadAudio {
+    pub async fn new(xo_chip: bool) -> Self {
+        let beep = match load_sound("./sound.ogg").await {
+            Ok(sound) => Some(sound),
+            Err(e) => {
+                eprintln!("Error while loading sound file: {}", e);
+                None
+            },
+        };
+        Self {
+            xo_chip,
+            beep,
+            synthesizer: Synthesizer::new(),
+            rendered: None,
+            playing: false,
+        }
+    }
+
+    fn stop(&mut self) {
+        if let (true, Some((_, _, sound))) = (self.playing, &self.rendered) {
+            stop_sound(*sound);
+        }
+        self.playing = false;
+    }
+
+    fn play_pattern(&mut self, tone: Tone) {
+        let up_to_date = matches!(
+            &self.rendered,
+            Some((rendered_pattern, rendered_pitch, _))
+                if *rendered_pattern == tone.pattern && *rendered_pitch == tone.pitch
+        );
+        if !up_to_date {
+            self.stop();
+            let samples = self.synthesizer.render_loop(&tone.pattern, tone.pitch);
+            self.rendered = load_sound_now(&audio::to_wav(&samples))
+                .map(|sound| (tone.pattern, tone.pitch, sound));
+        }
+
+        if let (false, Some((_, _, sound))) = (self.playing, &self.rendered) {
+            play_sound(*sound, PlaySoundParams { looped: true, volume: 1.0 });
+            self.playing = true;
+        }
+    }
+}
+
+impl frontend::Audio for MacroquadAudio {
+    fn play(&mut self, tone: Option<Tone>) {
+        match tone {
+            None => self.stop(),
+            Some(tone) if self.xo_chip => self.play_pattern(tone),
+            Some(_) => {
+                if let Some(beep) = self.beep {
+                    play_sound_once(beep);
+                }
+            },
+        }
+    }
+}
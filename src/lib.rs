@@ -1,6 +1,24 @@
 mod stack;
 mod memory;
+pub mod screen;
+pub mod audio;
+pub mod font;
+pub mod error;
+pub mod rng;
+#[cfg(feature = "gui")]
 pub mod debug;
+#[cfg(feature = "gui")]
+pub mod gui;
+pub mod frontend;
 pub mod state;
+pub mod breakpoint;
+pub mod watchpoint;
 pub mod disasm;
 pub mod args;
+pub mod config;
+pub mod database;
+pub mod cartridge;
+pub mod savestate;
+pub mod rewind;
+pub mod undo;
+pub mod movie;
@@ -1,11 +1,18 @@
+use std::cmp;
 use std::mem::size_of;
 use core::fmt::Display;
 use std::ops::Shl;
 use std::ops::BitOr;
+use serde::{Deserialize, Serialize};
+use crate::error::Fault;
 
-#[derive(Clone)]
+pub const CHIP8_MEMORY_SIZE: usize = 0x1000;
+pub const XO_CHIP_MEMORY_SIZE: usize = 0x10000;
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Memory {
-    pub memory: [u8; 4096],
+    #[serde(with = "serde_bytes")]
+    pub memory: Vec<u8>,
 }
 
 impl Default for Memory {
@@ -16,31 +23,68 @@ impl Default for Memory {
 
 impl Memory {
     pub fn new() -> Self {
+        Self::with_size(CHIP8_MEMORY_SIZE)
+    }
+
+    pub fn with_size(size: usize) -> Self {
         Self {
-            memory: [0; 4096],
+            memory: vec![0; size],
         }
     }
 
-    pub fn from_vec(vec: Vec::<u8>) -> Self {
-        let mut memory = Self::new();
+    pub fn from_vec(vec: Vec::<u8>, size: usize) -> Self {
+        let mut memory = Self::with_size(size);
         for (i, &byte) in vec.iter().enumerate() {
             memory.memory[i] = byte;
         }
         memory
     }
 
-    pub fn write(&mut self, beg: usize, to_write: &[u8]) {
+    fn check_bounds(&self, beg: usize, len: usize) -> Result<(), Fault> {
+        if beg + len > self.memory.len() {
+            // Report the first address that is out of bounds
+            Err(Fault::MemoryOutOfBounds(cmp::max(beg, self.memory.len())))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Nothing gets written if any part of the range is out of bounds
+    pub fn write(&mut self, beg: usize, to_write: &[u8]) -> Result<(), Fault> {
+        self.check_bounds(beg, to_write.len())?;
         for (i, &byte) in to_write.iter().enumerate() {
             self.memory[beg+i] = byte;
         }
+        Ok(())
     }
 
-    pub fn read(&self, beg: usize, to_read: usize) -> Vec<u8> {
+    pub fn read(&self, beg: usize, to_read: usize) -> Result<Vec<u8>, Fault> {
+        self.check_bounds(beg, to_read)?;
         let mut ret = Vec::new();
         for &byte in self.memory[beg..beg+to_read].iter() {
             ret.push(byte);
         }
-        ret
+        Ok(ret)
+    }
+
+    /// Like write but addresses wrap around the end of the memory
+    pub fn write_wrapping(&mut self, beg: usize, to_write: &[u8]) {
+        let len = self.memory.len();
+        for (i, &byte) in to_write.iter().enumerate() {
+            self.memory[(beg+i) % len] = byte;
+        }
+    }
+
+    /// Like read but addresses wrap around the end of the memory
+    pub fn read_wrapping(&self, beg: usize, to_read: usize) -> Vec<u8> {
+        let len = self.memory.len();
+        (beg..beg+to_read).map(|addr| self.memory[addr % len]).collect()
+    }
+
+    /// Read a big-endian word, the way instructions are stored
+    pub fn read_u16(&self, beg: usize) -> Result<u16, Fault> {
+        self.check_bounds(beg, 2)?;
+        Ok(u16::from_be_bytes([self.memory[beg], self.memory[beg+1]]))
     }
 
     pub fn len(&self) -> usize {
@@ -48,8 +92,9 @@ impl Memory {
     }
 
     pub fn read_t<T: Default + Shl<usize, Output = T> + BitOr<u8, Output = T> + Display>
-        (&self, beg: usize) -> T {
+        (&self, beg: usize) -> Result<T, Fault> {
         let size = size_of::<T>();
+        self.check_bounds(beg, size)?;
         let mut number: T = T::default();
         // This might seem like a weird way to do this and you're right - it is.
         // However I can't find a way to easily get rid of the 'attempt to shift
@@ -65,6 +110,6 @@ impl Memory {
                 number = (number << 8) | byte;
             }
         }
-        number
+        Ok(number)
     }
 }
@@ -0,0 +1,226 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use crate::error::ErrorPolicy;
+use crate::frontend::Input;
+use crate::savestate::Machine;
+use crate::state::Chip8State;
+
+/// Name in the header of every movie, so other JSON files get a clear
+/// error
+const MOVIE_FORMAT: &str = "yayachip8rsemu movie";
+
+/// Bumped whenever the format or the emulation changes in a way that
+/// makes older movies play back differently
+pub const MOVIE_VERSION: u32 = 2;
+
+/// A hash of the state is stored this often, in frames
+const HASH_INTERVAL: u64 = 60;
+
+/// What identifies a movie, read on its own first like the header of
+/// a save state
+#[derive(Serialize, Deserialize)]
+struct Header {
+    format: String,
+    version: u32,
+    // SHA-1 of the ROM the movie was recorded with
+    rom: String,
+}
+
+/// A recording of the keys held during every frame, from a known
+/// starting state. Playing it back with the same ROM gives the exact
+/// same run. The seed and the quirks are a part of the starting state
+#[derive(Serialize, Deserialize)]
+struct Movie {
+    #[serde(flatten)]
+    header: Header,
+    instructions_per_frame: u32,
+    error_policy: ErrorPolicy,
+    start: Machine,
+    // Runs of frames with the same keys held: the keys as a bitmask
+    // with bit N for key N, and the amount of frames
+    input: Vec<(u16, u64)>,
+    // Hash of the state after the frame, by frame since the start
+    hashes: BTreeMap<u64, String>,
+}
+
+fn state_hash(state: &Chip8State) -> String {
+    let encoded = bincode::serialize(&Machine::from_state(state))
+        .expect("the machine state should always serialize");
+    Sha1::digest(encoded).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn key_mask(keys: &[bool; 16]) -> u16 {
+    keys.iter().enumerate()
+        .filter(|(_, &pressed)| pressed)
+        .fold(0, |mask, (key, _)| mask | 1 << key)
+}
+
+fn invalid_movie(message: String) -> IoError {
+    IoError::new(ErrorKind::InvalidData, format!("invalid movie: {}", message))
+}
+
+/// Records a movie while the emulation runs
+pub struct MovieRecorder {
+    movie: Movie,
+    frames: u64,
+}
+
+impl MovieRecorder {
+    /// Start recording from the current state
+    pub fn new(state: &Chip8State, rom_hash: &str) -> Self {
+        Self {
+            movie: Movie {
+                header: Header {
+                    format: MOVIE_FORMAT.to_owned(),
+                    version: MOVIE_VERSION,
+                    rom: rom_hash.to_owned(),
+                },
+                instructions_per_frame: state.instructions_per_frame,
+                error_policy: state.error_policy,
+                start: Machine::from_state(state),
+                input: Vec::new(),
+                hashes: BTreeMap::new(),
+            },
+            frames: 0,
+        }
+    }
+
+    /// Record a frame that was just run to its end, with the keys it
+    /// saw
+    pub fn record_frame(&mut self, state: &Chip8State) {
+        let keys = key_mask(&state.key_pressed);
+        match self.movie.input.last_mut() {
+            Some((last_keys, count)) if *last_keys == keys => *count += 1,
+            _ => self.movie.input.push((keys, 1)),
+        }
+        self.frames += 1;
+        if self.frames.is_multiple_of(HASH_INTERVAL) {
+            self.movie.hashes.insert(self.frames, state_hash(state));
+        }
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Write the movie, with the hash of the final state so the end of
+    /// the movie gets checked too. A state stopped in the middle of a
+    /// frame isn't the state after the last recorded one, so it isn't
+    /// hashed
+    pub fn save(&mut self, state: &Chip8State, path: &Path) -> IoResult<()> {
+        if self.frames > 0 && state.frame_cycle == 0 {
+            self.movie.hashes.entry(self.frames).or_insert_with(|| state_hash(state));
+        }
+        let json = serde_json::to_string(&self.movie).map_err(|e| invalid_movie(e.to_string()))?;
+        fs::write(path, json)
+    }
+}
+
+/// Plays a movie back. It's the input of the emulation, and checks
+/// the stored hashes after every frame to catch desyncs
+pub struct MoviePlayer {
+    movie: Movie,
+    // Frames played so far
+    frames: u64,
+    // Position in the runs of input
+    run: usize,
+    frame_in_run: u64,
+}
+
+impl MoviePlayer {
+    /// Load a movie. It has to be recorded by the same version and with
+    /// the same ROM
+    pub fn load(path: &Path, rom_hash: &str) -> IoResult<Self> {
+        let json = fs::read_to_string(path)
+            .map_err(|e| IoError::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+        let header: Header = serde_json::from_str(&json)
+            .map_err(|_| invalid_movie(format!("{} is not a movie", path.display())))?;
+        if header.format != MOVIE_FORMAT {
+            return Err(invalid_movie(format!("{} is not a movie", path.display())));
+        }
+        if header.version != MOVIE_VERSION {
+            return Err(invalid_movie(format!(
+                "{} has version {}, but only version {} is supported",
+                path.display(), header.version, MOVIE_VERSION
+            )));
+        }
+        if header.rom != rom_hash {
+            return Err(invalid_movie(format!(
+                "{} was recorded with another ROM (SHA-1 {}, this one is {})",
+                path.display(), header.rom, rom_hash
+            )));
+        }
+        let movie: Movie = serde_json::from_str(&json)
+            .map_err(|e| invalid_movie(format!("{}: {}", path.display(), e)))?;
+        Ok(Self { movie, frames: 0, run: 0, frame_in_run: 0 })
+    }
+
+    /// Put the state where the recording started, with the settings it
+    /// was recorded with
+    pub fn prepare(&self, state: &mut Chip8State) -> IoResult<()> {
+        self.movie.start.clone().restore(state)?;
+        state.instructions_per_frame = self.movie.instructions_per_frame;
+        state.error_policy = self.movie.error_policy;
+        Ok(())
+    }
+
+    pub fn instructions_per_frame(&self) -> u32 {
+        self.movie.instructions_per_frame
+    }
+
+    /// Length of the movie in frames
+    pub fn len(&self) -> u64 {
+        self.movie.input.iter().map(|(_, count)| count).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.run >= self.movie.input.len()
+    }
+
+    /// Call after every frame that was played to its end. Fails when
+    /// the state doesn't match the hash recorded for the frame
+    pub fn check_frame(&mut self, state: &Chip8State) -> Result<(), String> {
+        self.frames += 1;
+        match self.movie.hashes.get(&self.frames) {
+            Some(hash) => {
+                let actual = state_hash(state);
+                if actual == *hash {
+                    Ok(())
+                } else {
+                    Err(format!(
+                        "movie desynced at frame {}: the state hash is {}, {} was recorded",
+                        self.frames, actual, hash
+                    ))
+                }
+            },
+            None => Ok(()),
+        }
+    }
+}
+
+impl Input for MoviePlayer {
+    /// The keys of the next frame. Nothing is held after the end
+    fn keys(&mut self) -> [bool; 16] {
+        let Some(&(mask, count)) = self.movie.input.get(self.run) else {
+            return [false; 16];
+        };
+        self.frame_in_run += 1;
+        if self.frame_in_run >= count {
+            self.run += 1;
+            self.frame_in_run = 0;
+        }
+        std::array::from_fn(|key| mask & 1 << key != 0)
+    }
+}
@@ -0,0 +1,163 @@
+use std::collections::VecDeque;
+use crate::savestate::Machine;
+use crate::state::Chip8State;
+
+/// Default length of the rewind history
+pub const DEFAULT_REWIND_SECONDS: u32 = 300;
+
+/// Upper bound on the memory used by the history, on top of the
+/// amount of frames
+const MAX_HISTORY_BYTES: usize = 16 * 1024 * 1024;
+
+/// Unchanged parts of the state are skipped this many bytes at a time
+const BLOCK_SIZE: usize = 64;
+
+/// History of the machine state, one entry per frame. Only the newest
+/// state is kept whole, every older one is stored as the difference to
+/// the state after it: the XOR of both encodings, with the runs of
+/// zeros (the bytes that didn't change) squeezed out. Most frames only
+/// touch a few registers and pixels, so an entry is usually tens of
+/// bytes.
+pub struct Rewind {
+    capacity: usize,
+    newest: Option<Vec<u8>>,
+    // Oldest first
+    deltas: VecDeque<Vec<u8>>,
+    // Bytes used by the deltas
+    size: usize,
+}
+
+fn encode(state: &Chip8State) -> Vec<u8> {
+    bincode::serialize(&Machine::from_state(state))
+        .expect("the machine state should always serialize")
+}
+
+fn push_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(bytes: &[u8], pos: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = bytes[*pos];
+        *pos += 1;
+        value |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+/// Encode how to get from `to` back to `from`: the length of `from`,
+/// then pairs of (zeros to skip, literal XOR bytes). The encodings may
+/// differ in length, the shorter one is padded with zeros.
+fn diff(from: &[u8], to: &[u8]) -> Vec<u8> {
+    let len = from.len().max(to.len());
+    let common = from.len().min(to.len());
+    let xor = |i: usize| from.get(i).unwrap_or(&0) ^ to.get(i).unwrap_or(&0);
+    let mut out = Vec::new();
+    push_varint(&mut out, from.len());
+    let mut i = 0;
+    while i < len {
+        let zeros_start = i;
+        while i + BLOCK_SIZE <= common && from[i..i + BLOCK_SIZE] == to[i..i + BLOCK_SIZE] {
+            i += BLOCK_SIZE;
+        }
+        while i < len && xor(i) == 0 {
+            i += 1;
+        }
+        let literal_start = i;
+        while i < len && xor(i) != 0 {
+            i += 1;
+        }
+        push_varint(&mut out, literal_start - zeros_start);
+        push_varint(&mut out, i - literal_start);
+        out.extend((literal_start..i).map(xor));
+    }
+    out
+}
+
+/// Undo `diff`, turning `to` into `from`
+fn apply(to: &mut Vec<u8>, delta: &[u8]) {
+    let mut pos = 0;
+    let from_len = read_varint(delta, &mut pos);
+    if to.len() < from_len {
+        to.resize(from_len, 0);
+    }
+    let mut i = 0;
+    while pos < delta.len() {
+        i += read_varint(delta, &mut pos);
+        let literal_len = read_varint(delta, &mut pos);
+        for byte in to[i..i + literal_len].iter_mut() {
+            *byte ^= delta[pos];
+            pos += 1;
+        }
+        i += literal_len;
+    }
+    to.truncate(from_len);
+}
+
+impl Rewind {
+    /// A history of at most `capacity` frames. Zero disables it
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            newest: None,
+            deltas: VecDeque::new(),
+            size: 0,
+        }
+    }
+
+    /// Record the state after a frame
+    pub fn push(&mut self, state: &Chip8State) {
+        if self.capacity == 0 {
+            return;
+        }
+        let current = encode(state);
+        if let Some(newest) = &self.newest {
+            let delta = diff(newest, &current);
+            self.size += delta.len();
+            self.deltas.push_back(delta);
+        }
+        self.newest = Some(current);
+        while self.deltas.len() > self.capacity || self.size > MAX_HISTORY_BYTES {
+            match self.deltas.pop_front() {
+                Some(delta) => self.size -= delta.len(),
+                None => break,
+            }
+        }
+    }
+
+    /// Go one frame back. Returns false when there's no more history
+    pub fn rewind(&mut self, state: &mut Chip8State) -> bool {
+        let (Some(newest), Some(delta)) = (&mut self.newest, self.deltas.pop_back()) else {
+            return false;
+        };
+        self.size -= delta.len();
+        apply(newest, &delta);
+        let machine = bincode::deserialize::<Machine>(newest)
+            .expect("the rewind history should always deserialize");
+        machine.restore(state).expect("the rewind history should match the machine");
+        true
+    }
+
+    /// Frames that can be rewound
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    /// Bytes used by the history
+    pub fn size(&self) -> usize {
+        self.size + self.newest.as_ref().map_or(0, Vec::len)
+    }
+}
@@ -0,0 +1,93 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// Where the COSMAC VIP interpreter keeps its own code. Its random
+/// routine reads bytes from this page as a source of noise
+const VIP_INTERPRETER_PAGE: usize = 0x100;
+
+/// Seed used when nobody asks for a particular one, so the library is
+/// deterministic by default
+pub const DEFAULT_SEED: u64 = 0;
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Serialize, Deserialize, Debug)]
+pub enum RngMode {
+    /// xorshift64*, good quality numbers
+    Xorshift,
+    /// The routine used by the original COSMAC VIP interpreter
+    Vip,
+}
+
+/// Random number generator used by CXNN. It's a part of the emulator
+/// state, so cloning the state (e.g. for a snapshot) makes the
+/// following random numbers repeat exactly.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Rng {
+    mode: RngMode,
+    seed: u64,
+    state: u64,
+    // R9 of the VIP interpreter. The low byte is a pointer into the
+    // interpreter page, the high byte is the last random number
+    vip_state: u16,
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Self::new(DEFAULT_SEED, RngMode::Xorshift)
+    }
+}
+
+impl Rng {
+    pub fn new(seed: u64, mode: RngMode) -> Self {
+        // splitmix64 of the seed, so small seeds give good starting
+        // states too and the state is never zero
+        let mut state = seed.wrapping_add(0x9e3779b97f4a7c15);
+        state = (state ^ (state >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        state = (state ^ (state >> 27)).wrapping_mul(0x94d049bb133111eb);
+        state ^= state >> 31;
+        Self {
+            mode,
+            seed,
+            state: if state == 0 { 1 } else { state },
+            vip_state: seed as u16,
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn mode(&self) -> RngMode {
+        self.mode
+    }
+
+    /// Get the next random byte. The VIP mode reads the interpreter
+    /// page from the emulated memory, so it needs access to it
+    pub fn next_u8(&mut self, memory: &[u8]) -> u8 {
+        match self.mode {
+            RngMode::Xorshift => self.next_xorshift(),
+            RngMode::Vip => self.next_vip(memory),
+        }
+    }
+
+    fn next_xorshift(&mut self) -> u8 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        (self.state.wrapping_mul(0x2545f4914f6cdd1d) >> 56) as u8
+    }
+
+    /// Modelled after the VIP routine. The low byte of R9 is
+    /// incremented and used to pick a byte from the interpreter page,
+    /// which is added to the high byte together with the low byte. The
+    /// sum is rotated and becomes both the new high byte and the
+    /// result. On the VIP that page holds the interpreter itself, here
+    /// it holds whatever the frontend loaded there.
+    fn next_vip(&mut self, memory: &[u8]) -> u8 {
+        let [high, low] = self.vip_state.to_be_bytes();
+        let low = low.wrapping_add(1);
+        let noise = memory.get(VIP_INTERPRETER_PAGE + low as usize).copied().unwrap_or(0);
+        let high = high.wrapping_add(noise).wrapping_add(low).rotate_right(1);
+        self.vip_state = u16::from_be_bytes([high, low]);
+        high
+    }
+}
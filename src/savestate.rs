@@ -0,0 +1,181 @@
+use std::fs;
+use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::audio::PATTERN_SIZE;
+use crate::memory::Memory;
+use crate::rng::Rng;
+use crate::screen::Screen;
+use crate::stack::Stack;
+use crate::state::{Chip8State, KeypressWait, QuirksConfig};
+
+/// Name in the header of every save state, so other JSON files get a
+/// clear error
+const SAVE_STATE_FORMAT: &str = "yayachip8rsemu save state";
+
+/// Bumped whenever the saved fields change. States of other versions
+/// are rejected
+pub const SAVE_STATE_VERSION: u32 = 1;
+
+/// What identifies a save state. It's read on its own first, so a
+/// state of another version is reported as such instead of as a
+/// parse error
+#[derive(Serialize, Deserialize)]
+struct Header {
+    format: String,
+    version: u32,
+    // SHA-1 of the ROM the state was saved with
+    rom: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SaveState {
+    #[serde(flatten)]
+    header: Header,
+    machine: Machine,
+}
+
+/// The part of Chip8State that belongs to the emulated machine. The
+/// debugger state, like the breakpoints, and the settings coming from
+/// the command line stay as they are when loading. Also used by the
+/// rewind buffer and the movies
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Machine {
+    pc: u16,
+    reg: [u8; 16],
+    addr: u16,
+    font_addr: u16,
+    big_font_addr: u16,
+    stack: Stack,
+    memory: Memory,
+    delay_timer: u8,
+    sound_timer: u8,
+    audio_pattern: [u8; PATTERN_SIZE],
+    pitch: u8,
+    screen: Screen,
+    rng: Rng,
+    rpl_flags: [u8; 16],
+    keypress_wait: KeypressWait,
+    waiting_for_vblank: bool,
+    exited: bool,
+    cycles: u64,
+    frames: u64,
+    frame_cycle: u32,
+    quirks_config: QuirksConfig,
+}
+
+impl Machine {
+    pub(crate) fn from_state(state: &Chip8State) -> Self {
+        Self {
+            pc: state.pc,
+            reg: state.reg,
+            addr: state.addr,
+            font_addr: state.font_addr,
+            big_font_addr: state.big_font_addr,
+            stack: state.stack.clone(),
+            memory: state.memory.clone(),
+            delay_timer: state.delay_timer,
+            sound_timer: state.sound_timer,
+            audio_pattern: state.audio_pattern,
+            pitch: state.pitch,
+            screen: state.screen.clone(),
+            rng: state.rng.clone(),
+            rpl_flags: state.rpl_flags,
+            keypress_wait: state.keypress_wait,
+            waiting_for_vblank: state.waiting_for_vblank,
+            exited: state.exited,
+            cycles: state.cycles,
+            frames: state.frames,
+            frame_cycle: state.frame_cycle,
+            quirks_config: state.quirks_config.clone(),
+        }
+    }
+
+    pub(crate) fn restore(self, state: &mut Chip8State) -> IoResult<()> {
+        if self.memory.memory.len() != self.quirks_config.platform.memory_size() {
+            return Err(invalid_state(format!(
+                "{} bytes of memory don't match {:?}",
+                self.memory.memory.len(), self.quirks_config.platform
+            )));
+        }
+        self.stack.validate().map_err(invalid_state)?;
+        self.screen.validate().map_err(invalid_state)?;
+        if let KeypressWait::Release(key) = self.keypress_wait {
+            if key >= 16 {
+                return Err(invalid_state(format!("FX0A waits for key {}, there are 16", key)));
+            }
+        }
+        state.pc = self.pc;
+        state.reg = self.reg;
+        state.addr = self.addr;
+        state.font_addr = self.font_addr;
+        state.big_font_addr = self.big_font_addr;
+        state.stack = self.stack;
+        state.memory = self.memory;
+        state.delay_timer = self.delay_timer;
+        state.sound_timer = self.sound_timer;
+        state.audio_pattern = self.audio_pattern;
+        state.pitch = self.pitch;
+        state.screen = self.screen;
+        state.rng = self.rng;
+        state.rpl_flags = self.rpl_flags;
+        state.keypress_wait = self.keypress_wait;
+        state.waiting_for_vblank = self.waiting_for_vblank;
+        state.exited = self.exited;
+        state.cycles = self.cycles;
+        state.frames = self.frames;
+        state.frame_cycle = self.frame_cycle;
+        state.quirks_config = self.quirks_config;
+        state.error = None;
+        // The recorded instructions led to the replaced state
+        if let Some(undo_log) = &mut state.undo_log {
+            undo_log.clear();
+        }
+        Ok(())
+    }
+}
+
+fn invalid_state(message: String) -> IoError {
+    IoError::new(ErrorKind::InvalidData, format!("invalid save state: {}", message))
+}
+
+/// Write the state of the machine running the ROM with the given hash
+pub fn save(state: &Chip8State, rom_hash: &str, path: &Path) -> IoResult<()> {
+    let save_state = SaveState {
+        header: Header {
+            format: SAVE_STATE_FORMAT.to_owned(),
+            version: SAVE_STATE_VERSION,
+            rom: rom_hash.to_owned(),
+        },
+        machine: Machine::from_state(state),
+    };
+    let json = serde_json::to_string(&save_state).map_err(|e| invalid_state(e.to_string()))?;
+    fs::write(path, json)
+}
+
+/// Load a state into the machine. The state has to be saved by the
+/// same version of the format and with the same ROM
+pub fn load(state: &mut Chip8State, rom_hash: &str, path: &Path) -> IoResult<()> {
+    let json = fs::read_to_string(path)
+        .map_err(|e| IoError::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+    let header: Header = serde_json::from_str(&json)
+        .map_err(|_| invalid_state(format!("{} is not a save state", path.display())))?;
+    if header.format != SAVE_STATE_FORMAT {
+        return Err(invalid_state(format!("{} is not a save state", path.display())));
+    }
+    if header.version != SAVE_STATE_VERSION {
+        return Err(invalid_state(format!(
+            "{} has version {}, but only version {} is supported",
+            path.display(), header.version, SAVE_STATE_VERSION
+        )));
+    }
+    if header.rom != rom_hash {
+        return Err(invalid_state(format!(
+            "{} was saved with another ROM (SHA-1 {}, this one is {})",
+            path.display(), header.rom, rom_hash
+        )));
+    }
+    let save_state: SaveState = serde_json::from_str(&json)
+        .map_err(|e| invalid_state(format!("{}: {}", path.display(), e)))?;
+    save_state.machine.restore(state)
+}
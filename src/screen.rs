@@ -0,0 +1,284 @@
+use serde::{Deserialize, Serialize};
+
+pub const LORES_WIDTH: usize = 64;
+pub const LORES_HEIGHT: usize = 32;
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+/// XO-CHIP has two bitplanes
+pub const PLANES: u8 = 2;
+pub const ALL_PLANES: u8 = 0b11;
+
+/// Colors for all the possible pixel values as 0xRRGGBB. The first
+/// entry is the background, then the first plane, the second plane
+/// and the pixels set on both planes.
+pub type Palette = [u32; 4];
+
+pub const DEFAULT_PALETTE: Palette = [0x4f4f4f, 0xc77aff, 0x00e430, 0xfdf900];
+
+/// Characters used for the pixel values when dumping the screen as
+/// text
+const ASCII_PIXELS: [char; 4] = ['.', '#', '+', '@'];
+
+/// Parse a palette given as four comma separated RRGGBB colors
+pub fn parse_palette(palette: &str) -> Result<Palette, String> {
+    let mut colors = Vec::new();
+    for color in palette.split(',') {
+        let color = color.trim().trim_start_matches('#');
+        match u32::from_str_radix(color, 16) {
+            Ok(value) if color.len() == 6 => colors.push(value),
+            _ => return Err(format!("{} is not a RRGGBB color", color)),
+        }
+    }
+    colors.try_into()
+        .map_err(|colors: Vec<u32>| format!("expected 4 colors, got {}", colors.len()))
+}
+
+/// Serde only handles arrays of up to 32 elements, so the pixels are
+/// saved as one flat list of bytes
+mod pixels {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+    use serde_bytes::ByteBuf;
+    use super::{HIRES_HEIGHT, HIRES_WIDTH};
+
+    pub fn serialize<S: Serializer>(
+        pixels: &[[u8; HIRES_WIDTH]; HIRES_HEIGHT],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(pixels.as_flattened())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<[[u8; HIRES_WIDTH]; HIRES_HEIGHT], D::Error> {
+        let flat = ByteBuf::deserialize(deserializer)?;
+        if flat.len() != HIRES_WIDTH * HIRES_HEIGHT {
+            return Err(de::Error::invalid_length(flat.len(), &"128*64 pixels"));
+        }
+        let mut pixels = [[0; HIRES_WIDTH]; HIRES_HEIGHT];
+        for (row, chunk) in pixels.iter_mut().zip(flat.chunks(HIRES_WIDTH)) {
+            row.copy_from_slice(chunk);
+        }
+        Ok(pixels)
+    }
+}
+
+/// CHIP-8 framebuffer. The buffer is always big enough for the
+/// SUPER-CHIP 128x64 hi-res mode, in lo-res mode only the top left
+/// 64x32 part of it is used. Every pixel holds a bit for each of the
+/// XO-CHIP planes.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Screen {
+    hires: bool,
+    // Bitmask of the planes affected by drawing, clearing and
+    // scrolling
+    planes: u8,
+    #[serde(with = "pixels")]
+    pixels: [[u8; HIRES_WIDTH]; HIRES_HEIGHT],
+}
+
+/// What a change to the screen overwrote, so it can be undone
+#[derive(Clone)]
+pub(crate) struct ScreenUndo {
+    hires: bool,
+    planes: u8,
+    // Index into the flattened pixels and the old value
+    pixels: Vec<(u16, u8)>,
+}
+
+impl Default for Screen {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Screen {
+    pub fn new() -> Self {
+        Self {
+            hires: false,
+            planes: 1,
+            pixels: [[0; HIRES_WIDTH]; HIRES_HEIGHT],
+        }
+    }
+
+    /// Check a screen that wasn't drawn by the interpreter, e.g. from a
+    /// save state
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.planes > ALL_PLANES {
+            return Err(format!("planes {:#b} aren't XO-CHIP planes", self.planes));
+        }
+        if let Some(pixel) = self.pixels.as_flattened().iter().find(|&&pixel| pixel > ALL_PLANES) {
+            return Err(format!("pixel value {} is more than the planes can hold", pixel));
+        }
+        Ok(())
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    /// Switch between the lo-res and hi-res mode. Like in Octo the
+    /// screen gets cleared on every switch.
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.pixels = [[0; HIRES_WIDTH]; HIRES_HEIGHT];
+    }
+
+    pub fn planes(&self) -> u8 {
+        self.planes
+    }
+
+    pub fn select_planes(&mut self, planes: u8) {
+        self.planes = planes & ALL_PLANES;
+    }
+
+    pub fn width(&self) -> usize {
+        if self.hires { HIRES_WIDTH } else { LORES_WIDTH }
+    }
+
+    pub fn height(&self) -> usize {
+        if self.hires { HIRES_HEIGHT } else { LORES_HEIGHT }
+    }
+
+    /// Get the pixel value, which is also an index into the palette
+    pub fn get(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y][x]
+    }
+
+    /// Xor a pixel on a single plane with the given bit. Returns true
+    /// if the pixel got erased, so the caller can set the collision
+    /// flag.
+    pub fn xor(&mut self, x: usize, y: usize, plane: u8, bit: bool) -> bool {
+        let before = self.pixels[y][x] & plane != 0;
+        if bit {
+            self.pixels[y][x] ^= plane;
+        }
+        before && bit
+    }
+
+    pub fn clear(&mut self) {
+        for line in self.pixels.iter_mut() {
+            for pixel in line.iter_mut() {
+                *pixel &= !self.planes;
+            }
+        }
+    }
+
+    /// Move the selected planes' bit from one pixel to another, the
+    /// source being None means the pixel scrolled in from outside of
+    /// the screen
+    fn move_pixel(&mut self, from: Option<(usize, usize)>, to: (usize, usize)) {
+        let bits = match from {
+            Some((x, y)) => self.pixels[y][x] & self.planes,
+            None => 0,
+        };
+        let (x, y) = to;
+        self.pixels[y][x] = (self.pixels[y][x] & !self.planes) | bits;
+    }
+
+    pub fn scroll_down(&mut self, n: usize) {
+        let (width, height) = (self.width(), self.height());
+        for y in (0..height).rev() {
+            for x in 0..width {
+                let from = if y >= n { Some((x, y-n)) } else { None };
+                self.move_pixel(from, (x, y));
+            }
+        }
+    }
+
+    pub fn scroll_up(&mut self, n: usize) {
+        let (width, height) = (self.width(), self.height());
+        for y in 0..height {
+            for x in 0..width {
+                let from = if y+n < height { Some((x, y+n)) } else { None };
+                self.move_pixel(from, (x, y));
+            }
+        }
+    }
+
+    pub fn scroll_right(&mut self, n: usize) {
+        let (width, height) = (self.width(), self.height());
+        for y in 0..height {
+            for x in (0..width).rev() {
+                let from = if x >= n { Some((x-n, y)) } else { None };
+                self.move_pixel(from, (x, y));
+            }
+        }
+    }
+
+    pub fn scroll_left(&mut self, n: usize) {
+        let (width, height) = (self.width(), self.height());
+        for y in 0..height {
+            for x in 0..width {
+                let from = if x+n < width { Some((x+n, y)) } else { None };
+                self.move_pixel(from, (x, y));
+            }
+        }
+    }
+
+    /// The visible part of the screen as text, one line per row
+    pub fn to_ascii(&self) -> String {
+        let mut ascii = String::with_capacity((self.width() + 1) * self.height());
+        for y in 0..self.height() {
+            for x in 0..self.width() {
+                ascii.push(ASCII_PIXELS[self.get(x, y) as usize]);
+            }
+            ascii.push('\n');
+        }
+        ascii
+    }
+
+    /// The visible part of the screen as a plain PBM image. PBM only
+    /// has two colors, so any pixel set on any plane is black
+    pub fn to_pbm(&self) -> String {
+        let mut pbm = format!("P1\n{} {}\n", self.width(), self.height());
+        for y in 0..self.height() {
+            let row: Vec<&str> = (0..self.width())
+                .map(|x| if self.get(x, y) != 0 { "1" } else { "0" })
+                .collect();
+            pbm += &row.join(" ");
+            pbm.push('\n');
+        }
+        pbm
+    }
+
+    /// The visible part of the screen as 0xRRGGBB colors, row by row
+    pub fn to_rgb(&self, palette: &Palette) -> Vec<u8> {
+        let mut rgb = Vec::with_capacity(self.width() * self.height() * 3);
+        for y in 0..self.height() {
+            for x in 0..self.width() {
+                let [_, r, g, b] = palette[self.get(x, y) as usize].to_be_bytes();
+                rgb.extend_from_slice(&[r, g, b]);
+            }
+        }
+        rgb
+    }
+
+    /// What has to be undone to get back to the earlier screen. None
+    /// if nothing changed
+    pub(crate) fn undo_since(&self, before: &Screen) -> Option<ScreenUndo> {
+        if self.hires == before.hires && self.planes == before.planes && self.pixels == before.pixels {
+            return None;
+        }
+        let mut pixels = Vec::new();
+        for (y, (row, row_before)) in self.pixels.iter().zip(before.pixels.iter()).enumerate() {
+            if row == row_before {
+                continue;
+            }
+            for (x, (&pixel, &pixel_before)) in row.iter().zip(row_before.iter()).enumerate() {
+                if pixel != pixel_before {
+                    pixels.push(((y * HIRES_WIDTH + x) as u16, pixel_before));
+                }
+            }
+        }
+        Some(ScreenUndo { hires: before.hires, planes: before.planes, pixels })
+    }
+
+    pub(crate) fn undo(&mut self, undo: &ScreenUndo) {
+        self.hires = undo.hires;
+        self.planes = undo.planes;
+        for &(index, pixel) in undo.pixels.iter() {
+            let index = index as usize;
+            self.pixels[index / HIRES_WIDTH][index % HIRES_WIDTH] = pixel;
+        }
+    }
+}
@@ -1,7 +1,12 @@
-#[derive(Clone)]
+use serde::{Deserialize, Serialize};
+use crate::error::Fault;
+
+const STACK_SIZE: usize = 16;
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Stack {
     sp: usize,
-    stack: [u16; 16],
+    stack: [u16; STACK_SIZE],
 }
 
 impl Default for Stack {
@@ -14,17 +19,47 @@ impl Stack {
     pub fn new() -> Self {
         Self {
             sp: 0,
-            stack: [0u16; 16],
+            stack: [0u16; STACK_SIZE],
+        }
+    }
+
+    /// Check a stack that didn't come from push and pop, e.g. from a
+    /// save state
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.sp > STACK_SIZE {
+            return Err(format!("stack pointer {} is past the {} entries of the stack", self.sp, STACK_SIZE));
         }
+        Ok(())
     }
 
-    pub fn push(&mut self, val: u16) {
+    pub fn push(&mut self, val: u16) -> Result<(), Fault> {
+        if self.sp == STACK_SIZE {
+            return Err(Fault::StackOverflow);
+        }
         self.stack[self.sp] = val;
         self.sp += 1;
+        Ok(())
     }
 
-    pub fn pop(&mut self) -> u16 {
+    pub fn pop(&mut self) -> Result<u16, Fault> {
+        if self.sp == 0 {
+            return Err(Fault::StackUnderflow);
+        }
         self.sp -= 1;
+        Ok(self.stack[self.sp])
+    }
+
+    /// Like push but the stack pointer wraps around instead of
+    /// overflowing
+    pub fn push_wrapping(&mut self, val: u16) {
+        self.stack[self.sp % STACK_SIZE] = val;
+        self.sp = (self.sp + 1) % STACK_SIZE;
+    }
+
+    /// Like pop but the stack pointer wraps around instead of
+    /// underflowing
+    pub fn pop_wrapping(&mut self) -> u16 {
+        self.sp = (self.sp + STACK_SIZE - 1) % STACK_SIZE;
         self.stack[self.sp]
     }
 }
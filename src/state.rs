@@ -1,31 +1,68 @@
 use std::cmp;
+use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+use serde::{Deserialize, Serialize};
+use crate::breakpoint::Breakpoint;
 use crate::disasm::Instruction;
 use crate::stack::Stack;
-use crate::memory::Memory;
-use rand::Rng;
+use crate::memory::{Memory, CHIP8_MEMORY_SIZE, XO_CHIP_MEMORY_SIZE};
+use crate::screen::{Screen, PLANES};
+use crate::audio::{PATTERN_SIZE, DEFAULT_PATTERN, DEFAULT_PITCH};
+use crate::font::{Font, DEFAULT_FONT_ADDR, SMALL_GLYPH_SIZE, BIG_GLYPH_SIZE};
+use crate::error::{ErrorPolicy, ExecutionError, Fault};
+use crate::rng::Rng;
+use crate::undo::UndoLog;
+use crate::watchpoint::{Access, WatchHit, Watchpoint};
 
-#[derive(Clone)]
-pub struct Breakpoint {
-    pub addr: u16,
+/// Around 1000 instructions per second at 60 frames per second. CHIP-8
+/// doesn't really have a set cpu frequency but according to a random
+/// reddit post some ROMs might be frequency sensitive, so it seems
+/// like a nice middleground
+/// https://www.reddit.com/r/EmuDev/comments/gvmk12/comment/fsq9p8a/
+pub const DEFAULT_INSTRUCTIONS_PER_FRAME: u32 = 16;
+
+/// Progress of the blocking FX0A instruction
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Debug)]
+pub enum KeypressWait {
+    NotWaiting,
+    // Waiting for any key to be pressed
+    Press,
+    // The key was pressed, now waiting for it to be released
+    Release(u8),
 }
 
-impl Breakpoint {
-    pub fn new(addr: u16) -> Self {
-        Self {
-            addr
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Debug)]
+pub enum Platform {
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+impl Platform {
+    pub fn memory_size(&self) -> usize {
+        match self {
+            Self::XoChip => XO_CHIP_MEMORY_SIZE,
+            _ => CHIP8_MEMORY_SIZE,
         }
     }
-}
 
-impl cmp::PartialEq for Breakpoint {
-    fn eq(&self, other: &Self) -> bool {
-        self.addr == other.addr
+    /// Fails when a ROM this long doesn't fit in the memory if it's
+    /// loaded at the offset
+    pub fn check_rom_size(&self, len: usize, offset: u16) -> IoResult<()> {
+        let available = self.memory_size().saturating_sub(offset as usize);
+        if len > available {
+            return Err(IoError::new(ErrorKind::InvalidData, format!(
+                "ROM is {} bytes long, but only {} bytes are available when loading at {:#x} on {:?}",
+                len, available, offset, self
+            )));
+        }
+        Ok(())
     }
 }
 
 /// https://github.com/Timendus/chip8-test-suite?tab=readme-ov-file#quirks-test
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct QuirksConfig {
+    pub platform: Platform,
     pub vf_reset: bool,
     pub memory: bool,
     pub display_wait: bool,
@@ -37,6 +74,7 @@ pub struct QuirksConfig {
 impl QuirksConfig {
     pub fn get_chip8() -> Self {
         Self {
+            platform: Platform::Chip8,
             vf_reset: true,
             memory: true,
             display_wait: true,
@@ -48,6 +86,7 @@ impl QuirksConfig {
 
     pub fn get_xo_chip() -> Self {
         Self {
+            platform: Platform::XoChip,
             vf_reset: false,
             memory: true,
             display_wait: false,
@@ -59,6 +98,7 @@ impl QuirksConfig {
 
     pub fn get_super_chip() -> Self {
         Self {
+            platform: Platform::SuperChip,
             vf_reset: false,
             memory: false,
             display_wait: false,
@@ -67,6 +107,24 @@ impl QuirksConfig {
             jumping: true,
         }
     }
+
+    /// Turn a single quirk on or off by its name
+    pub fn set_quirk(&mut self, name: &str, value: bool) -> Result<(), String> {
+        let quirk = match name {
+            "vf_reset" => &mut self.vf_reset,
+            "memory" => &mut self.memory,
+            "display_wait" => &mut self.display_wait,
+            "clipping" => &mut self.clipping,
+            "shifting" => &mut self.shifting,
+            "jumping" => &mut self.jumping,
+            _ => return Err(format!(
+                "unknown quirk {}, expected one of: vf_reset, memory, display_wait, clipping, shifting, jumping",
+                name
+            )),
+        };
+        *quirk = value;
+        Ok(())
+    }
 }
 
 #[derive(Clone)]
@@ -77,17 +135,55 @@ pub struct Chip8State {
     pub key_pressed: [bool; 16],
     // The I address register
     pub addr: u16,
+    // Where the 4x5 font used by FX29 is loaded
+    pub font_addr: u16,
+    // Where the 8x10 font used by FX30 is loaded
+    pub big_font_addr: u16,
     pub stack: Stack,
     pub memory: Memory,
     pub delay_timer: u8,
     pub sound_timer: u8,
-    pub screen: [[bool; 64]; 32],
-    pub keypress_reg: u8,
+    // XO-CHIP audio, played while sound_timer is non-zero
+    pub audio_pattern: [u8; PATTERN_SIZE],
+    pub pitch: u8,
+    pub screen: Screen,
+    // Used by CXNN
+    pub rng: Rng,
+    // HP48 RPL user flags used by FX75 and FX85. Frontends should
+    // persist them when rpl_flags_changed gets set
+    pub rpl_flags: [u8; 16],
+    pub rpl_flags_changed: bool,
+    pub keypress_wait: KeypressWait,
+    // Set after drawing when the display_wait quirk is on, cleared on
+    // the next 60Hz display interrupt
+    pub waiting_for_vblank: bool,
     pub stop: bool,
+    pub error_policy: ErrorPolicy,
+    // Error that halted the execution
+    pub error: Option<ExecutionError>,
+    // Set by the SUPER-CHIP 00FD instruction
+    pub exited: bool,
     pub steps_to_stop: u16,
     pub breakpoints: Vec<Breakpoint>,
+    pub watchpoints: Vec<Watchpoint>,
+    // The watched access that stopped the execution last, cleared when
+    // it continues
+    pub watch_hit: Option<WatchHit>,
+    // Address and value of the first watched access of the instruction
+    // being executed
+    pending_watch: Option<(Access, u16, u8)>,
+    // Emulated clock. A cycle is one instruction, a frame is one 60Hz
+    // display interrupt which also ticks the timers
+    pub cycles: u64,
+    pub frames: u64,
+    pub instructions_per_frame: u32,
+    // Cycles executed since the last frame boundary
+    pub frame_cycle: u32,
     pub time_multiplier: f64,
     pub quirks_config: QuirksConfig,
+    // Side effects of the last instructions, for stepping back. None
+    // when nothing is recorded
+    pub undo_log: Option<UndoLog>,
 }
 
 impl Default for Chip8State {
@@ -97,48 +193,64 @@ impl Default for Chip8State {
 }
 
 impl Chip8State {
+    /// Empty memory with only the VIP font at DEFAULT_FONT_ADDR, so
+    /// FX29 and FX30 work. Args::create_chip8 loads the chosen font
+    /// instead
     pub fn new(quirks_cofig: QuirksConfig) -> Self {
-        Self {
+        let mut chip8_state = Self {
             pc: 0,
             reg: [0; 16],
             key_pressed: [false; 16],
             addr: 0,
+            font_addr: DEFAULT_FONT_ADDR,
+            big_font_addr: DEFAULT_FONT_ADDR + 16*SMALL_GLYPH_SIZE,
             stack: Stack::new(),
-            memory: Memory::new(),
+            memory: Memory::with_size(quirks_cofig.platform.memory_size()),
             delay_timer: 0,
             sound_timer: 0,
-            screen: [[false; 64]; 32],
-            keypress_reg: 0,
+            audio_pattern: DEFAULT_PATTERN,
+            pitch: DEFAULT_PITCH,
+            screen: Screen::new(),
+            rng: Rng::default(),
+            rpl_flags: [0; 16],
+            rpl_flags_changed: false,
+            keypress_wait: KeypressWait::NotWaiting,
+            waiting_for_vblank: false,
             stop: false,
+            error_policy: ErrorPolicy::Halt,
+            error: None,
+            exited: false,
             steps_to_stop: 0,
             breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            watch_hit: None,
+            pending_watch: None,
+            cycles: 0,
+            frames: 0,
+            instructions_per_frame: DEFAULT_INSTRUCTIONS_PER_FRAME,
+            frame_cycle: 0,
             time_multiplier: 1.0,
             quirks_config: quirks_cofig,
-        }
+            undo_log: None,
+        };
+        chip8_state.memory.write(DEFAULT_FONT_ADDR as usize, &Font::Vip.memory_image())
+            .expect("the font should fit in the memory of every platform");
+        chip8_state
     }
 
-    pub fn from_memory(quirks_cofig: QuirksConfig, memory: Vec<u8>) -> Self {
-        Self {
-            pc: 0,
-            reg: [0; 16],
-            key_pressed: [false; 16],
-            addr: 0,
-            stack: Stack::new(),
-            memory: Memory::from_vec(memory),
-            delay_timer: 0,
-            sound_timer: 0,
-            screen: [[false; 64]; 32],
-            keypress_reg: 0,
-            stop: false,
-            steps_to_stop: 0,
-            breakpoints: Vec::new(),
-            time_multiplier: 1.0,
-            quirks_config: quirks_cofig,
-        }
+    /// Like new, with the memory image loaded from address 0 over the
+    /// font. Fails when the image is bigger than the platform's memory
+    pub fn from_memory(quirks_cofig: QuirksConfig, memory: Vec<u8>) -> IoResult<Self> {
+        quirks_cofig.platform.check_rom_size(memory.len(), 0)?;
+        let mut chip8_state = Self::new(quirks_cofig);
+        chip8_state.memory.memory[..memory.len()].copy_from_slice(&memory);
+        Ok(chip8_state)
     }
 
     pub fn step(&mut self, steps: u16) {
         self.stop = false;
+        self.error = None;
+        self.watch_hit = None;
         self.steps_to_stop += steps;
     }
 
@@ -149,17 +261,26 @@ impl Chip8State {
 
     pub fn continue_execution(&mut self) {
         self.stop = false;
+        self.error = None;
+        self.watch_hit = None;
         self.steps_to_stop = 0;
     }
 
+    /// Count a hit for every breakpoint the execution is at, and stop
+    /// once one of them has been hit more times than it ignores
     pub fn check_for_breakpoints(&mut self) {
-        for bp in self.breakpoints.iter() {
-            if bp.addr == self.pc {
-                self.stop = true;
-                self.steps_to_stop = 0;
-                break;
+        let mut stop = false;
+        for i in 0..self.breakpoints.len() {
+            if self.breakpoints[i].matches(self) {
+                let bp = &mut self.breakpoints[i];
+                bp.hit_count += 1;
+                stop |= bp.hit_count > bp.ignore_count;
             }
         }
+        if stop {
+            self.stop = true;
+            self.steps_to_stop = 0;
+        }
     }
 
     /// Get information about registers as a String. Used for
@@ -174,6 +295,21 @@ impl Chip8State {
         state_str += &format!("I: {0:#06x}\n", self.addr);
         state_str += &format!("delay_timer: {:3}\n", self.delay_timer);
         state_str += &format!("sound_timer: {:3}\n", self.sound_timer);
+        state_str += &format!("rng: {:?} seed {}\n", self.rng.mode(), self.rng.seed());
+        state_str += &format!("cycles: {} frames: {}\n", self.cycles, self.frames);
+        if self.waiting_for_vblank {
+            state_str += "waiting for vblank\n";
+        }
+        if let Some(error) = &self.error {
+            state_str += &format!("error: {}\n", error);
+        }
+        match self.keypress_wait {
+            KeypressWait::NotWaiting => {},
+            KeypressWait::Press => state_str += "waiting for key press\n",
+            KeypressWait::Release(key) => {
+                state_str += &format!("waiting for key {:x} release\n", key);
+            },
+        }
         state_str
     }
 
@@ -181,28 +317,38 @@ impl Chip8State {
     /// pointer. Used for debugging purposes
     pub fn get_disassembly_string(&self) -> String {
         let mut disasm_str = String::new();
-        for i in (-6..=18).step_by(2) {
-            let inst_addr = self.pc as i32 + i;
-            if inst_addr < 0 || inst_addr as usize >= self.memory.len() - 1 {
-                disasm_str += "\n";
-                continue;
-            }
-            let inst = self.memory.read(inst_addr as usize, 2);
-            let bytes = [inst[0], inst[1]];
-            let word = u16::from_be_bytes(bytes);
-            let instruction = Instruction::from(word);
+        // Instructions before pc can't be decoded reliably when there
+        // are four bytes long instructions around, so just assume all
+        // of them are two bytes long
+        for i in (-6..0).step_by(2) {
+            disasm_str += &self.get_disassembly_line(self.pc as i32 + i).0;
+        }
+        let mut inst_addr = self.pc as i32;
+        for i in 0..10 {
+            let (line, size) = self.get_disassembly_line(inst_addr);
             if i == 0 {
                 disasm_str += "--->  ";
             }
-            disasm_str += &format!("{:04x}:\t{:04x} {}\n", inst_addr, word, instruction);
+            disasm_str += &line;
+            inst_addr += size as i32;
         }
         disasm_str
     }
 
-    pub fn load_memory(&mut self, to_load: Vec<u8>, offset: usize) {
-        for (i, &byte) in to_load.iter().enumerate() {
-            self.memory.memory[i+offset] = byte;
+    fn get_disassembly_line(&self, inst_addr: i32) -> (String, usize) {
+        if inst_addr < 0 || inst_addr as usize >= self.memory.len() - 1 {
+            return (String::from("\n"), 2);
         }
+        let to_read = cmp::min(4, self.memory.len() - inst_addr as usize);
+        let bytes = self.memory.read_wrapping(inst_addr as usize, to_read);
+        let instruction = Instruction::decode(&bytes, &self.quirks_config);
+        let size = instruction.size();
+        let hex: String = bytes[..size].iter().map(|b| format!("{:02x}", b)).collect();
+        (format!("{:04x}:\t{} {}\n", inst_addr, hex, instruction), size)
+    }
+
+    pub fn load_memory(&mut self, to_load: Vec<u8>, offset: usize) -> Result<(), Fault> {
+        self.memory.write(offset, &to_load)
     }
 
     pub fn add_breakpoint(&mut self, breakpoint_addr: u16) {
@@ -210,286 +356,508 @@ impl Chip8State {
         self.breakpoints.push(bp);
     }
 
-    pub fn emulate_instruction(&mut self) {
-        if !self.stop {
-            self.execute_instruction();
+    /// Signal the 60Hz display interrupt, which ends the wait started
+    /// by the display_wait quirk
+    pub fn vblank(&mut self) {
+        self.waiting_for_vblank = false;
+    }
 
-            if self.steps_to_stop > 0 {
-                self.steps_to_stop -= 1;
-                if self.steps_to_stop == 0 {
-                    self.stop = true;
-                }
+    /// End the current frame: decrement the timers and signal the
+    /// display interrupt. Called by emulate_instruction on frame
+    /// boundaries, so frontends don't have to
+    pub fn tick_frame(&mut self) {
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+        self.vblank();
+        self.frames += 1;
+        self.frame_cycle = 0;
+    }
+
+    pub fn is_running(&self) -> bool {
+        !self.stop && !self.exited
+    }
+
+    /// Emulate instructions until the end of the current frame, or
+    /// until the execution gets stopped
+    pub fn run_frame(&mut self, instructions_per_frame: u32) -> Result<(), ExecutionError> {
+        self.instructions_per_frame = instructions_per_frame;
+        let frame = self.frames;
+        while self.frames == frame && self.is_running() {
+            self.emulate_instruction()?;
+        }
+        Ok(())
+    }
+
+    pub fn emulate_instruction(&mut self) -> Result<(), ExecutionError> {
+        if !self.is_running() {
+            return Ok(());
+        }
+
+        // FX0A waiting for a key is executed again and again, the whole
+        // wait is undone at once
+        let recording = match self.keypress_wait {
+            KeypressWait::NotWaiting => self.start_undo_recording(),
+            _ => None,
+        };
+        let result = self.advance();
+        if let Some(recording) = recording {
+            self.finish_undo_recording(recording);
+        }
+        result
+    }
+
+    /// Execute the next instruction, or wait for the display interrupt
+    fn advance(&mut self) -> Result<(), ExecutionError> {
+        let instructions_per_frame = self.instructions_per_frame.max(1);
+        if self.waiting_for_vblank {
+            // Nothing happens until the display interrupt, so skip
+            // right to it
+            self.cycles += instructions_per_frame.saturating_sub(self.frame_cycle) as u64;
+            self.tick_frame();
+            return Ok(());
+        }
+
+        if let Err(error) = self.execute_instruction() {
+            self.stop_execution();
+            self.error = Some(error);
+            return Err(error);
+        }
+        self.cycles += 1;
+        self.frame_cycle += 1;
+        if self.frame_cycle >= instructions_per_frame {
+            self.tick_frame();
+        }
+
+        if self.steps_to_stop > 0 {
+            self.steps_to_stop -= 1;
+            if self.steps_to_stop == 0 {
+                self.stop = true;
             }
+        }
 
+        // Still waiting for a key means the same instruction is next,
+        // it was already checked when the execution got to it
+        if self.keypress_wait == KeypressWait::NotWaiting {
             self.check_for_breakpoints();
         }
+        Ok(())
     }
 
-    pub fn execute_instruction(&mut self) {
-        let inst = self.memory.read(self.pc as usize, 2);
-        let bytes = [inst[0], inst[1]];
-        let inst = u16::from_be_bytes(bytes);
-        Self::find_instruction_func(self, inst)(self, inst);
-    }
-
-    pub fn find_instruction_func(&self, inst: u16) -> fn(&mut Self, u16) {
-        if inst == 0x00e0 {
-            Self::clear_display
-        } else if inst == 0x00ee {
-            Self::return_from_subroutine
-        } else if inst & 0xf000 == 0x0000 {
-            Self::call_rca1802_code_routine
-        } else if inst & 0xf000 == 0x1000 {
-            Self::jmp
-        } else if inst & 0xf000 == 0x2000 {
-            Self::call
-        } else if inst & 0xf000 == 0x3000 {
-            Self::skip_eq
-        } else if inst & 0xf000 == 0x4000 {
-            Self::skip_neq
-        } else if inst & 0xf000 == 0x5000 && inst & 0x000f == 0x0 {
-            Self::skip_regs_eq
-        } else if inst & 0xf000 == 0x6000 {
-            Self::set_val
-        } else if inst & 0xf000 == 0x7000 {
-            Self::add_val
-        } else if inst & 0xf000 == 0x8000 && inst & 0x000f == 0x0 {
-            Self::set_reg
-        } else if inst & 0xf000 == 0x8000 && inst & 0x000f == 0x1 {
-            Self::or_reg
-        } else if inst & 0xf000 == 0x8000 && inst & 0x000f == 0x2 {
-            Self::and_reg
-        } else if inst & 0xf000 == 0x8000 && inst & 0x000f == 0x3 {
-            Self::xor_reg
-        } else if inst & 0xf000 == 0x8000 && inst & 0x000f == 0x4 {
-            Self::add_reg
-        } else if inst & 0xf000 == 0x8000 && inst & 0x000f == 0x5 {
-            Self::sub_reg
-        } else if inst & 0xf000 == 0x8000 && inst & 0x000f == 0x6 {
-            Self::rsh_reg
-        } else if inst & 0xf000 == 0x8000 && inst & 0x000f == 0x7 {
-            Self::reverse_sub_reg
-        } else if inst & 0xf000 == 0x8000 && inst & 0x000f == 0xe {
-            Self::lsh_reg
-        } else if inst & 0xf000 == 0x9000 && inst & 0x000f == 0x0 {
-            Self::skip_regs_neq
-        } else if inst & 0xf000 == 0xa000 {
-            Self::set_addr
-        } else if inst & 0xf000 == 0xb000 {
-            if self.quirks_config.jumping {
-                // https://tobiasvl.github.io/blog/write-a-chip-8-emulator/#bnnn-jump-with-offset
-                Self::jmp_plus_xnn
-            } else {
-                Self::jmp_plus
+    pub fn execute_instruction(&mut self) -> Result<(), ExecutionError> {
+        let pc = self.pc;
+        let bytes = self.peek_memory(pc as usize, 2).map_err(|fault| fault.at(pc, 0))?;
+        let opcode = u16::from_be_bytes([bytes[0], bytes[1]]);
+        let size = Instruction::size_from_opcode(opcode, &self.quirks_config);
+        // Watchpoints see only the first fetch of FX0A waiting for a key
+        let fetched = match self.keypress_wait {
+            KeypressWait::NotWaiting => self.access_memory(Access::Execute, pc as usize, size),
+            _ => self.peek_memory(pc as usize, size),
+        };
+        let result = fetched.and_then(|bytes| {
+            let instruction = Instruction::decode(&bytes, &self.quirks_config);
+            let result = self.dispatch(instruction);
+            self.report_watch_hit(pc, instruction);
+            result
+        });
+        let result = match (result, self.error_policy) {
+            (Ok(()), _) => Ok(()),
+            (Err(fault), ErrorPolicy::Halt) => Err(fault.at(pc, opcode)),
+            // Instructions fault before changing anything, so skipping
+            // over them is enough to make them a NOP
+            (Err(_), ErrorPolicy::Nop | ErrorPolicy::Wrap) => {
+                self.pc = pc.wrapping_add(size as u16);
+                Ok(())
+            },
+        };
+        // Like the other addresses the program counter wraps around the
+        // end of the memory
+        if self.error_policy == ErrorPolicy::Wrap {
+            self.pc = (self.pc as usize % self.memory.len()) as u16;
+        }
+        result
+    }
+
+    /// Read memory respecting the error policy, without the access
+    /// being seen by the watchpoints
+    fn peek_memory(&self, addr: usize, len: usize) -> Result<Vec<u8>, Fault> {
+        match self.error_policy {
+            ErrorPolicy::Wrap => Ok(self.memory.read_wrapping(addr, len)),
+            _ => self.memory.read(addr, len),
+        }
+    }
+
+    /// All memory accesses done by instructions go through here and
+    /// write_memory, so the error policy is respected and the
+    /// watchpoints see them
+    fn access_memory(&mut self, access: Access, addr: usize, len: usize) -> Result<Vec<u8>, Fault> {
+        let bytes = self.peek_memory(addr, len)?;
+        self.check_watchpoints(access, addr, &bytes);
+        Ok(bytes)
+    }
+
+    fn read_memory(&mut self, addr: usize, len: usize) -> Result<Vec<u8>, Fault> {
+        self.access_memory(Access::Read, addr, len)
+    }
+
+    fn write_memory(&mut self, addr: usize, data: &[u8]) -> Result<(), Fault> {
+        if self.undo_log.is_some() {
+            let old = self.peek_memory(addr, data.len());
+            let len = self.memory.len();
+            if let (Ok(old), Some(undo_log)) = (old, self.undo_log.as_mut()) {
+                for (i, &byte) in old.iter().enumerate() {
+                    undo_log.record_write((addr + i) % len, byte);
+                }
             }
-        } else if inst & 0xf000 == 0xc000 {
-            Self::rand
-        } else if inst & 0xf000 == 0xd000 {
-            Self::draw
-        } else if inst & 0xf000 == 0xe000 && inst & 0x00ff == 0x9e {
-            Self::skip_if_pressed
-        } else if inst & 0xf000 == 0xe000 && inst & 0x00ff == 0xa1 {
-            Self::skip_if_not_pressed
-        } else if inst & 0xf000 == 0xf000 && inst & 0x00ff == 0x07 {
-            Self::get_delay_timer
-        } else if inst & 0xf000 == 0xf000 && inst & 0x00ff == 0x0a {
-            Self::get_keypress
-        } else if inst & 0xf000 == 0xf000 && inst & 0x00ff == 0x15 {
-            Self::set_delay_timer
-        } else if inst & 0xf000 == 0xf000 && inst & 0x00ff == 0x18 {
-            Self::set_sound_timer
-        } else if inst & 0xf000 == 0xf000 && inst & 0x00ff == 0x1e {
-            Self::add_to_addr
-        } else if inst & 0xf000 == 0xf000 && inst & 0x00ff == 0x29 {
-            Self::set_addr_to_sprite_addr
-        } else if inst & 0xf000 == 0xf000 && inst & 0x00ff == 0x33 {
-            Self::store_bcd
-        } else if inst & 0xf000 == 0xf000 && inst & 0x00ff == 0x55 {
-            Self::reg_dump
-        } else if inst & 0xf000 == 0xf000 && inst & 0x00ff == 0x65 {
-            Self::reg_load
-        } else {
-            panic!("bad instruction {:04x}", inst);
+        }
+        match self.error_policy {
+            ErrorPolicy::Wrap => self.memory.write_wrapping(addr, data),
+            _ => self.memory.write(addr, data)?,
+        }
+        self.check_watchpoints(Access::Write, addr, data);
+        Ok(())
+    }
+
+    /// Count a hit for every watchpoint the access touches, and keep
+    /// the first watched byte until the instruction is done
+    fn check_watchpoints(&mut self, access: Access, addr: usize, bytes: &[u8]) {
+        if self.watchpoints.is_empty() {
+            return;
+        }
+        let len = self.memory.len();
+        for watchpoint in self.watchpoints.iter_mut() {
+            let hit = bytes.iter().enumerate()
+                .map(|(i, &value)| ((addr + i) % len, value))
+                .find(|&(addr, _)| watchpoint.watches(access, addr));
+            if let Some((addr, value)) = hit {
+                watchpoint.hit_count += 1;
+                self.pending_watch.get_or_insert((access, addr as u16, value));
+            }
+        }
+    }
+
+    /// Stop the execution when the instruction that was just executed
+    /// did a watched access
+    fn report_watch_hit(&mut self, pc: u16, instruction: Instruction) {
+        if let Some((access, addr, value)) = self.pending_watch.take() {
+            self.watch_hit = Some(WatchHit {
+                access,
+                addr,
+                value,
+                pc,
+                instruction: instruction.to_string(),
+            });
+            self.stop_execution();
         }
     }
 
+    fn push_stack(&mut self, val: u16) -> Result<(), Fault> {
+        match self.error_policy {
+            ErrorPolicy::Wrap => {
+                self.stack.push_wrapping(val);
+                Ok(())
+            },
+            _ => self.stack.push(val),
+        }
+    }
+
+    fn pop_stack(&mut self) -> Result<u16, Fault> {
+        match self.error_policy {
+            ErrorPolicy::Wrap => Ok(self.stack.pop_wrapping()),
+            _ => self.stack.pop(),
+        }
+    }
+
+    /// Run a decoded instruction. Whether it exists on the current
+    /// platform was already decided by the decoder
+    fn dispatch(&mut self, instruction: Instruction) -> Result<(), Fault> {
+        match instruction {
+            Instruction::CallRca1802CodeRoutine { .. } => self.call_rca1802_code_routine(),
+            Instruction::ClearDisplay => self.clear_display(),
+            Instruction::ReturnFromSubroutine => self.return_from_subroutine(),
+            Instruction::ScrollDown { n } => self.scroll_down(n),
+            Instruction::ScrollUp { n } => self.scroll_up(n),
+            Instruction::ScrollRight => self.scroll_right(),
+            Instruction::ScrollLeft => self.scroll_left(),
+            Instruction::Exit => self.exit(),
+            Instruction::Lores => self.lores(),
+            Instruction::Hires => self.hires(),
+            Instruction::Jmp { nnn } => self.jmp(nnn),
+            Instruction::Call { nnn } => self.call(nnn),
+            Instruction::SkipEq { x, nn } => self.skip_eq(x, nn),
+            Instruction::SkipNeq { x, nn } => self.skip_neq(x, nn),
+            Instruction::SkipRegsEq { x, y } => self.skip_regs_eq(x, y),
+            Instruction::SaveRegsRange { x, y } => self.save_regs_range(x, y),
+            Instruction::LoadRegsRange { x, y } => self.load_regs_range(x, y),
+            Instruction::SetVal { x, nn } => self.set_val(x, nn),
+            Instruction::AddVal { x, nn } => self.add_val(x, nn),
+            Instruction::SetReg { x, y } => self.set_reg(x, y),
+            Instruction::OrReg { x, y } => self.or_reg(x, y),
+            Instruction::AndReg { x, y } => self.and_reg(x, y),
+            Instruction::XorReg { x, y } => self.xor_reg(x, y),
+            Instruction::AddReg { x, y } => self.add_reg(x, y),
+            Instruction::SubReg { x, y } => self.sub_reg(x, y),
+            Instruction::RshReg { x, y } => self.rsh_reg(x, y),
+            Instruction::ReverseSubReg { x, y } => self.reverse_sub_reg(x, y),
+            Instruction::LshReg { x, y } => self.lsh_reg(x, y),
+            Instruction::SkipRegsNeq { x, y } => self.skip_regs_neq(x, y),
+            Instruction::SetAddr { nnn } => self.set_addr(nnn),
+            Instruction::LongSetAddr { nnnn } => self.long_set_addr(nnnn),
+            Instruction::JmpPlus { nnn } => self.jmp_plus(nnn),
+            Instruction::JmpPlusX { x, nnn } => self.jmp_plus_x(x, nnn),
+            Instruction::Rand { x, nn } => self.rand(x, nn),
+            Instruction::Draw { x, y, n } => self.draw(x, y, n),
+            Instruction::SkipIfPressed { x } => self.skip_if_pressed(x),
+            Instruction::SkipIfNotPressed { x } => self.skip_if_not_pressed(x),
+            Instruction::SelectPlanes { n } => self.select_planes(n),
+            Instruction::LoadAudioPattern => self.load_audio_pattern(),
+            Instruction::GetDelayTimer { x } => self.get_delay_timer(x),
+            Instruction::GetKeypress { x } => self.get_keypress(x),
+            Instruction::SetDelayTimer { x } => self.set_delay_timer(x),
+            Instruction::SetSoundTimer { x } => self.set_sound_timer(x),
+            Instruction::AddToAddr { x } => self.add_to_addr(x),
+            Instruction::SetAddrToSpriteAddr { x } => self.set_addr_to_sprite_addr(x),
+            Instruction::SetAddrToBigSpriteAddr { x } => self.set_addr_to_big_sprite_addr(x),
+            Instruction::StoreBcd { x } => self.store_bcd(x),
+            Instruction::SetPitch { x } => self.set_pitch(x),
+            Instruction::RegDump { x } => self.reg_dump(x),
+            Instruction::RegLoad { x } => self.reg_load(x),
+            Instruction::SaveRplFlags { x } => self.save_rpl_flags(x),
+            Instruction::LoadRplFlags { x } => self.load_rpl_flags(x),
+            Instruction::Unknown { .. } => Err(Fault::UnknownOpcode),
+        }
+    }
+
+    /// Skip the next instruction. On XO-CHIP it has to take into
+    /// account the four bytes long F000 NNNN instruction
+    fn skip_next_instruction(&mut self) {
+        let next_addr = self.pc as usize + 2;
+        let next_size = match self.memory.read_u16(next_addr) {
+            Ok(opcode) => Instruction::size_from_opcode(opcode, &self.quirks_config),
+            Err(_) => 2,
+        };
+        self.pc = self.pc.wrapping_add(2 + next_size as u16);
+    }
+
     // Below you can see functions corresponding to the various
-    // instructions. Made based on the opcode table from:
-    // https://en.wikipedia.org/wiki/CHIP-8#Opcode_table
-    
+    // instructions. The operands come already decoded, see
+    // Instruction::decode
+
     // 0NNN
-    pub fn call_rca1802_code_routine(&mut self, inst: u16) {
-        assert_eq!((inst & 0xf000) >> 12, 0);
-        self.pc += 2;
+    pub fn call_rca1802_code_routine(&mut self) -> Result<(), Fault> {
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
     }
 
     // 00E0
-    pub fn clear_display(&mut self, inst: u16) {
-        assert_eq!(inst, 0x00e0);
-        self.screen = [[false; 64]; 32];
-        self.pc += 2;
+    pub fn clear_display(&mut self) -> Result<(), Fault> {
+        self.screen.clear();
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
     }
 
     // 00ee
-    pub fn return_from_subroutine(&mut self, inst: u16) {
-        assert_eq!(inst, 0x00ee);
-        let addr = self.stack.pop();
+    pub fn return_from_subroutine(&mut self) -> Result<(), Fault> {
+        let addr = self.pop_stack()?;
         self.pc = addr;
+        Ok(())
+    }
+
+    // 00CN
+    pub fn scroll_down(&mut self, n: u8) -> Result<(), Fault> {
+        self.screen.scroll_down(n as usize);
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
+    }
+
+    // 00DN
+    pub fn scroll_up(&mut self, n: u8) -> Result<(), Fault> {
+        self.screen.scroll_up(n as usize);
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
+    }
+
+    // 00FB
+    pub fn scroll_right(&mut self) -> Result<(), Fault> {
+        self.screen.scroll_right(4);
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
+    }
+
+    // 00FC
+    pub fn scroll_left(&mut self) -> Result<(), Fault> {
+        self.screen.scroll_left(4);
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
+    }
+
+    // 00FD
+    pub fn exit(&mut self) -> Result<(), Fault> {
+        // pc is left on the instruction on purpose, so the debugger
+        // shows where the program ended
+        self.exited = true;
+        Ok(())
+    }
+
+    // 00FE
+    pub fn lores(&mut self) -> Result<(), Fault> {
+        self.screen.set_hires(false);
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
+    }
+
+    // 00FF
+    pub fn hires(&mut self) -> Result<(), Fault> {
+        self.screen.set_hires(true);
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
     }
 
     // 1NNN
-    pub fn jmp(&mut self, inst: u16) {
-        assert_eq!((inst & 0xf000) >> 12, 1);
-        let nnn = inst & 0x0fff;
+    pub fn jmp(&mut self, nnn: u16) -> Result<(), Fault> {
         self.pc = nnn;
+        Ok(())
     }
 
     // 2NNN
-    pub fn call(&mut self, inst: u16) {
-        assert_eq!((inst & 0xf000) >> 12, 2);
-        self.stack.push(self.pc + 2);
-        let nnn = inst & 0x0fff;
+    pub fn call(&mut self, nnn: u16) -> Result<(), Fault> {
+        self.push_stack(self.pc.wrapping_add(2))?;
         self.pc = nnn;
+        Ok(())
     }
 
     // 3XNN
-    pub fn skip_eq(&mut self, inst: u16) {
-        assert_eq!((inst & 0xf000) >> 12, 3);
-        let nn = (inst & 0x00ff) as u8;
-        let x = ((inst & 0x0f00) >> 8) as usize;
+    pub fn skip_eq(&mut self, x: usize, nn: u8) -> Result<(), Fault> {
         if self.reg[x] == nn {
-            self.pc += 4;
+            self.skip_next_instruction();
         } else {
-            self.pc += 2;
+            self.pc = self.pc.wrapping_add(2);
         }
+        Ok(())
     }
 
     // 4XNN
-    pub fn skip_neq(&mut self, inst: u16) {
-        assert_eq!((inst & 0xf000) >> 12, 4);
-        let nn = (inst & 0x00ff) as u8;
-        let x = ((inst & 0x0f00) >> 8) as usize;
+    pub fn skip_neq(&mut self, x: usize, nn: u8) -> Result<(), Fault> {
         if self.reg[x] != nn {
-            self.pc += 4;
+            self.skip_next_instruction();
         } else {
-            self.pc += 2;
+            self.pc = self.pc.wrapping_add(2);
         }
+        Ok(())
     }
 
     // 5XY0
-    pub fn skip_regs_eq(&mut self, inst: u16) {
-        assert_eq!((inst & 0xf000) >> 12, 5);
-        assert_eq!(inst & 0x000f, 0);
-        let x = ((inst & 0x0f00) >> 8) as usize;
-        let y = ((inst & 0x00f0) >> 4) as usize;
+    pub fn skip_regs_eq(&mut self, x: usize, y: usize) -> Result<(), Fault> {
         if self.reg[x] == self.reg[y] {
-            self.pc += 4;
+            self.skip_next_instruction();
+        } else {
+            self.pc = self.pc.wrapping_add(2);
+        }
+        Ok(())
+    }
+
+    // 5XY2
+    pub fn save_regs_range(&mut self, x: usize, y: usize) -> Result<(), Fault> {
+        // The range can be given in the descending order too
+        let regs: Vec<u8> = if x <= y {
+            self.reg[x..=y].to_vec()
         } else {
-            self.pc += 2;
+            self.reg[y..=x].iter().rev().copied().collect()
+        };
+        self.write_memory(self.addr as usize, &regs)?;
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
+    }
+
+    // 5XY3
+    pub fn load_regs_range(&mut self, x: usize, y: usize) -> Result<(), Fault> {
+        let count = x.abs_diff(y) + 1;
+        let readed = self.read_memory(self.addr as usize, count)?;
+        for (i, &value) in readed.iter().enumerate() {
+            if x <= y {
+                self.reg[x+i] = value;
+            } else {
+                self.reg[x-i] = value;
+            }
         }
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
     }
 
     // 6XNN
-    pub fn set_val(&mut self, inst: u16) {
-        assert_eq!((inst & 0xf000) >> 12, 6);
-        let x = ((inst & 0x0f00) >> 8) as usize;
-        let nn: u8 = (inst & 0x00ff) as u8;
+    pub fn set_val(&mut self, x: usize, nn: u8) -> Result<(), Fault> {
         self.reg[x] = nn;
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
     }
 
     // 7XNN
-    pub fn add_val(&mut self, inst: u16) {
-        assert_eq!((inst & 0xf000) >> 12, 7);
-        let x = ((inst & 0x0f00) >> 8) as usize;
-        let nn: u8 = (inst & 0x00ff) as u8;
+    pub fn add_val(&mut self, x: usize, nn: u8) -> Result<(), Fault> {
         self.reg[x] = self.reg[x].wrapping_add(nn);
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
     }
 
     // 8XY0
-    pub fn set_reg(&mut self, inst: u16) {
-        assert_eq!((inst & 0xf000) >> 12, 8);
-        assert_eq!(inst & 0x000f, 0);
-        let x = ((inst & 0x0f00) >> 8) as usize;
-        let y = ((inst & 0x00f0) >> 4) as usize;
+    pub fn set_reg(&mut self, x: usize, y: usize) -> Result<(), Fault> {
         self.reg[x] = self.reg[y];
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
     }
 
     // 8XY1
-    pub fn or_reg(&mut self, inst: u16) {
-        assert_eq!((inst & 0xf000) >> 12, 8);
-        assert_eq!(inst & 0x000f, 1);
-        let x = ((inst & 0x0f00) >> 8) as usize;
-        let y = ((inst & 0x00f0) >> 4) as usize;
+    pub fn or_reg(&mut self, x: usize, y: usize) -> Result<(), Fault> {
         self.reg[x] |= self.reg[y];
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
         
         if self.quirks_config.vf_reset {
             self.reg[0xf] = 0;
         }
+        Ok(())
     }
 
     // 8XY2
-    pub fn and_reg(&mut self, inst: u16) {
-        assert_eq!((inst & 0xf000) >> 12, 8);
-        assert_eq!(inst & 0x000f, 2);
-        let x = ((inst & 0x0f00) >> 8) as usize;
-        let y = ((inst & 0x00f0) >> 4) as usize;
+    pub fn and_reg(&mut self, x: usize, y: usize) -> Result<(), Fault> {
         self.reg[x] &= self.reg[y];
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
         
         if self.quirks_config.vf_reset {
             self.reg[0xf] = 0;
         }
+        Ok(())
     }
 
     // 8XY3
-    pub fn xor_reg(&mut self, inst: u16) {
-        assert_eq!((inst & 0xf000) >> 12, 8);
-        assert_eq!(inst & 0x000f, 3);
-        let x = ((inst & 0x0f00) >> 8) as usize;
-        let y = ((inst & 0x00f0) >> 4) as usize;
+    pub fn xor_reg(&mut self, x: usize, y: usize) -> Result<(), Fault> {
         self.reg[x] ^= self.reg[y];
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
         
         if self.quirks_config.vf_reset {
             self.reg[0xf] = 0;
         }
+        Ok(())
     }
 
     // 8XY4
-    pub fn add_reg(&mut self, inst: u16) {
-        assert_eq!((inst & 0xf000) >> 12, 8);
-        assert_eq!(inst & 0x000f, 4);
-        let x = ((inst & 0x0f00) >> 8) as usize;
-        let y = ((inst & 0x00f0) >> 4) as usize;
+    pub fn add_reg(&mut self, x: usize, y: usize) -> Result<(), Fault> {
         let (result, carry) = self.reg[x].overflowing_add(self.reg[y]);
         self.reg[x] = result;
         self.reg[0xf] = carry as u8;
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
     }
 
     // 8XY5
-    pub fn sub_reg(&mut self, inst: u16) {
-        assert_eq!((inst & 0xf000) >> 12, 8);
-        assert_eq!(inst & 0x000f, 5);
-        let x = ((inst & 0x0f00) >> 8) as usize;
-        let y = ((inst & 0x00f0) >> 4) as usize;
+    pub fn sub_reg(&mut self, x: usize, y: usize) -> Result<(), Fault> {
         let (result, borrow) = self.reg[x].overflowing_sub(self.reg[y]);
         self.reg[x] = result;
         self.reg[0xf] = !borrow as u8;
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
     }
 
     // 8XY6
-    pub fn rsh_reg(&mut self, inst: u16) {
-        assert_eq!((inst & 0xf000) >> 12, 8);
-        assert_eq!(inst & 0x000f, 6);
-        let x = ((inst & 0x0f00) >> 8) as usize;
+    pub fn rsh_reg(&mut self, x: usize, y: usize) -> Result<(), Fault> {
         if !self.quirks_config.shifting {
-            let y = ((inst & 0x00f0) >> 4) as usize;
             self.reg[x] = self.reg[y];
         }
         // So the order of operations is correct when performing operations
@@ -497,242 +865,287 @@ impl Chip8State {
         let tmp = self.reg[x] & 0x01;
         self.reg[x] >>= 1;
         self.reg[0xf] = tmp;
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
     }
 
     // 8XY7
-    pub fn reverse_sub_reg(&mut self, inst: u16) {
-        assert_eq!((inst & 0xf000) >> 12, 8);
-        assert_eq!(inst & 0x000f, 7);
-        let x = ((inst & 0x0f00) >> 8) as usize;
-        let y = ((inst & 0x00f0) >> 4) as usize;
+    pub fn reverse_sub_reg(&mut self, x: usize, y: usize) -> Result<(), Fault> {
         let (result, borrow) = self.reg[y].overflowing_sub(self.reg[x]);
         self.reg[x] = result;
         self.reg[0xf] = !borrow as u8;
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
     }
 
     // 8XYE
-    pub fn lsh_reg(&mut self, inst: u16) {
-        assert_eq!((inst & 0xf000) >> 12, 8);
-        assert_eq!(inst & 0x000f, 0xe);
-        let x = ((inst & 0x0f00) >> 8) as usize;
+    pub fn lsh_reg(&mut self, x: usize, y: usize) -> Result<(), Fault> {
         if !self.quirks_config.shifting {
-            let y = ((inst & 0x00f0) >> 4) as usize;
             self.reg[x] = self.reg[y];
         }
         // See comment on 8XY6
         let tmp = (self.reg[x] & 0x80) >> 7;
         self.reg[x] <<= 1;
         self.reg[0xf] = tmp;
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
     }
 
     // 9XY0
-    pub fn skip_regs_neq(&mut self, inst: u16) {
-        assert_eq!((inst & 0xf000) >> 12, 9);
-        assert_eq!(inst & 0x000f, 0);
-        let x = ((inst & 0x0f00) >> 8) as usize;
-        let y = ((inst & 0x00f0) >> 4) as usize;
+    pub fn skip_regs_neq(&mut self, x: usize, y: usize) -> Result<(), Fault> {
         if self.reg[x] != self.reg[y] {
-            self.pc += 4;
+            self.skip_next_instruction();
         } else {
-            self.pc += 2;
+            self.pc = self.pc.wrapping_add(2);
         }
+        Ok(())
     }
 
     // ANNN
-    pub fn set_addr(&mut self, inst: u16) {
-        assert_eq!((inst & 0xf000) >> 12, 0xa);
-        self.addr = inst & 0x0fff;
-        self.pc += 2;
+    pub fn set_addr(&mut self, nnn: u16) -> Result<(), Fault> {
+        self.addr = nnn;
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
     }
 
     // BNNN
-    pub fn jmp_plus(&mut self, inst: u16) {
-        assert_eq!((inst & 0xf000) >> 12, 0xb);
-        let nnn = inst & 0x0fff;
+    pub fn jmp_plus(&mut self, nnn: u16) -> Result<(), Fault> {
         self.pc = nnn + self.reg[0] as u16;
+        Ok(())
     }
 
     // BXNN
-    pub fn jmp_plus_xnn(&mut self, inst: u16) {
-        assert_eq!((inst & 0xf000) >> 12, 0xb);
-        let nnn = inst & 0x0fff;
-        let x = ((inst & 0x0f00) >> 8) as usize;
+    pub fn jmp_plus_x(&mut self, x: usize, nnn: u16) -> Result<(), Fault> {
         self.pc = nnn + self.reg[x] as u16;
+        Ok(())
     }
 
     // CXNN
-    pub fn rand(&mut self, inst: u16) {
-        assert_eq!((inst & 0xf000) >> 12, 0xc);
-        let x = ((inst & 0x0f00) >> 8) as usize;
-        let nn: u8 = (inst & 0xff) as u8;
-        self.reg[x] = rand::thread_rng().gen();
-        self.reg[x] &= nn;
-        self.pc += 2;
+    pub fn rand(&mut self, x: usize, nn: u8) -> Result<(), Fault> {
+        self.reg[x] = self.rng.next_u8(&self.memory.memory) & nn;
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
     }
 
     // DXYN
-    pub fn draw(&mut self, inst: u16) {
-        assert_eq!((inst & 0xf000) >> 12, 0xd);
-        let x = ((inst & 0x0f00) >> 8) as usize;
-        let y = ((inst & 0x00f0) >> 4) as usize;
-        let n: u8 = (inst & 0xf) as u8;
-
-        let y = self.reg[y] as usize % self.screen.len();
-        let x = self.reg[x] as usize % self.screen[y].len();
+    // DXY0 draws a 16x16 sprite (SUPER-CHIP), on the VIP it draws
+    // nothing
+    pub fn draw(&mut self, x: usize, y: usize, n: u8) -> Result<(), Fault> {
+        let (rows, row_bytes) = match n {
+            0 if self.quirks_config.platform != Platform::Chip8 => (16, 2),
+            _ => (n as usize, 1),
+        };
+
+        let width = self.screen.width();
+        let height = self.screen.height();
+        let y = self.reg[y] as usize % height;
+        let x = self.reg[x] as usize % width;
         let mut carry = 0;
-        for i in 0..n {
-            let byte = self.memory.read_t::<u8>(self.addr as usize + i as usize);
-            for j in 0..8 {
-                let mut y = y + i as usize;
-                let mut x = x + j as usize;
-                
-                if !self.quirks_config.clipping {
-                    y %= self.screen.len();
-                    x %= self.screen[y].len();
-                }
-                
-                if y >= self.screen.len() || x >= self.screen[0].len() {
-                    break;
-                }
-                
-                let bit = ((byte >> (7-j)) & 1) == 1;
-                let before = self.screen[y][x];
-                self.screen[y][x] ^= bit;
-                if before && !self.screen[y][x] {
-                    carry = 1;
+        // With multiple planes selected (XO-CHIP) the sprite data for
+        // each plane follows the previous one
+        let selected_planes = self.screen.planes().count_ones() as usize;
+        let sprite = self.read_memory(self.addr as usize, selected_planes*rows*row_bytes)?;
+        let mut sprite_rows = sprite.chunks(row_bytes);
+        for plane in (0..PLANES).map(|i| 1 << i) {
+            if self.screen.planes() & plane == 0 {
+                continue;
+            }
+            for (i, sprite_row) in sprite_rows.by_ref().take(rows).enumerate() {
+                for j in 0..row_bytes*8 {
+                    let mut y = y + i;
+                    let mut x = x + j;
+                    
+                    if !self.quirks_config.clipping {
+                        y %= height;
+                        x %= width;
+                    }
+                    
+                    if y >= height || x >= width {
+                        break;
+                    }
+                    
+                    let byte = sprite_row[j / 8];
+                    let bit = ((byte >> (7 - j%8)) & 1) == 1;
+                    if self.screen.xor(x, y, plane, bit) {
+                        carry = 1;
+                    }
                 }
             }
         }
         self.reg[0xf] = carry;
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
+
+        // On the COSMAC VIP drawing is synchronized with the display
+        // interrupt, so there's at most one sprite drawn per frame
+        if self.quirks_config.display_wait {
+            self.waiting_for_vblank = true;
+        }
+        Ok(())
     }
 
     // EX9E
-    pub fn skip_if_pressed(&mut self, inst: u16) {
-        assert_eq!((inst & 0xf000) >> 12, 0xe);
-        assert_eq!(inst & 0x00ff, 0x9e);
-        let x = ((inst & 0x0f00) >> 8) as usize;
-        if self.key_pressed[self.reg[x] as usize] {
-            self.pc += 4;
+    pub fn skip_if_pressed(&mut self, x: usize) -> Result<(), Fault> {
+        if self.key_pressed[(self.reg[x] & 0xf) as usize] {
+            self.skip_next_instruction();
         } else {
-            self.pc += 2;
+            self.pc = self.pc.wrapping_add(2);
         }
+        Ok(())
     }
 
     // EXA1
-    pub fn skip_if_not_pressed(&mut self, inst: u16) {
-        assert_eq!((inst & 0xf000) >> 12, 0xe);
-        assert_eq!(inst & 0x00ff, 0xa1);
-        let x = ((inst & 0x0f00) >> 8) as usize;
-        if !self.key_pressed[self.reg[x] as usize] {
-            self.pc += 4;
+    pub fn skip_if_not_pressed(&mut self, x: usize) -> Result<(), Fault> {
+        if !self.key_pressed[(self.reg[x] & 0xf) as usize] {
+            self.skip_next_instruction();
         } else {
-            self.pc += 2;
+            self.pc = self.pc.wrapping_add(2);
         }
+        Ok(())
+    }
+
+    // F000 NNNN
+    pub fn long_set_addr(&mut self, nnnn: u16) -> Result<(), Fault> {
+        self.addr = nnnn;
+        self.pc = self.pc.wrapping_add(4);
+        Ok(())
+    }
+
+    // FN01
+    pub fn select_planes(&mut self, n: u8) -> Result<(), Fault> {
+        self.screen.select_planes(n);
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
+    }
+
+    // F002
+    pub fn load_audio_pattern(&mut self) -> Result<(), Fault> {
+        let pattern = self.read_memory(self.addr as usize, PATTERN_SIZE)?;
+        self.audio_pattern.copy_from_slice(&pattern);
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
     }
 
     // FX07
-    pub fn get_delay_timer(&mut self, inst: u16) {
-        assert_eq!((inst & 0xf000) >> 12, 0xf);
-        assert_eq!(inst & 0x00ff, 7);
-        let x = ((inst & 0x0f00) >> 8) as usize;
+    pub fn get_delay_timer(&mut self, x: usize) -> Result<(), Fault> {
         self.reg[x] = self.delay_timer;
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
     }
 
     // FX0A
-    pub fn get_keypress(&mut self, inst: u16) {
-        assert_eq!((inst & 0xf000) >> 12, 0xf);
-        assert_eq!(inst & 0x00ff, 0x0a);
-        let x = ((inst & 0x0f00) >> 8) as usize;
-        self.keypress_reg = x as u8;
-        self.pc += 2;
+    // Blocks until a key is pressed and then released, like on the
+    // COSMAC VIP. pc isn't advanced until then, so the instruction
+    // gets executed again and again
+    pub fn get_keypress(&mut self, x: usize) -> Result<(), Fault> {
+        match self.keypress_wait {
+            KeypressWait::NotWaiting | KeypressWait::Press => {
+                let pressed = self.key_pressed.iter().position(|&pressed| pressed);
+                self.keypress_wait = match pressed {
+                    Some(key) => KeypressWait::Release(key as u8),
+                    None => KeypressWait::Press,
+                };
+            },
+            KeypressWait::Release(key) => {
+                if !self.key_pressed[key as usize] {
+                    self.reg[x] = key;
+                    self.keypress_wait = KeypressWait::NotWaiting;
+                    self.pc = self.pc.wrapping_add(2);
+                }
+            },
+        }
+        Ok(())
     }
 
     // FX15
-    pub fn set_delay_timer(&mut self, inst: u16) {
-        assert_eq!((inst & 0xf000) >> 12, 0xf);
-        assert_eq!(inst & 0x00ff, 0x15);
-        let x = ((inst & 0x0f00) >> 8) as usize;
+    pub fn set_delay_timer(&mut self, x: usize) -> Result<(), Fault> {
         self.delay_timer = self.reg[x];
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
     }
 
     // FX18
-    pub fn set_sound_timer(&mut self, inst: u16) {
-        assert_eq!((inst & 0xf000) >> 12, 0xf);
-        assert_eq!(inst & 0x00ff, 0x18);
-        let x = ((inst & 0x0f00) >> 8) as usize;
+    pub fn set_sound_timer(&mut self, x: usize) -> Result<(), Fault> {
         self.sound_timer = self.reg[x];
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
     }
 
     // FX1E
-    pub fn add_to_addr(&mut self, inst: u16) {
-        assert_eq!((inst & 0xf000) >> 12, 0xf);
-        assert_eq!(inst & 0x00ff, 0x1e);
-        let x = ((inst & 0x0f00) >> 8) as usize;
-        self.addr += self.reg[x] as u16;
-        self.pc += 2;
+    pub fn add_to_addr(&mut self, x: usize) -> Result<(), Fault> {
+        self.addr = self.addr.wrapping_add(self.reg[x] as u16);
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
     }
 
     // FX29
-    pub fn set_addr_to_sprite_addr(&mut self, inst: u16) {
-        println!("TODOTODOTODO");
-        // TODO
-        assert_eq!((inst & 0xf000) >> 12, 0xf);
-        assert_eq!(inst & 0x00ff, 0x29);
-        let _x = ((inst & 0x0f00) >> 8) as usize;
-        self.pc += 2;
+    pub fn set_addr_to_sprite_addr(&mut self, x: usize) -> Result<(), Fault> {
+        // Only the lowest nibble is used, just like on the COSMAC VIP
+        let digit = (self.reg[x] & 0xf) as u16;
+        self.addr = self.font_addr + digit*SMALL_GLYPH_SIZE;
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
+    }
+
+    // FX30
+    pub fn set_addr_to_big_sprite_addr(&mut self, x: usize) -> Result<(), Fault> {
+        let digit = (self.reg[x] & 0xf) as u16;
+        self.addr = self.big_font_addr + digit*BIG_GLYPH_SIZE;
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
     }
 
     // FX33
-    pub fn store_bcd(&mut self, inst: u16) {
-        assert_eq!((inst & 0xf000) >> 12, 0xf);
-        assert_eq!(inst & 0x00ff, 0x33);
-        let x = ((inst & 0x0f00) >> 8) as usize;
-        let mut number = self.reg[x];
-        for i in (0..=2).rev() {
-            let digit = number % 10;
-            number /= 10;
-            self.memory.write((self.addr+i) as usize, &digit.to_ne_bytes());
-        }
-        self.pc += 2;
+    pub fn store_bcd(&mut self, x: usize) -> Result<(), Fault> {
+        let number = self.reg[x];
+        let digits = [number / 100, number / 10 % 10, number % 10];
+        self.write_memory(self.addr as usize, &digits)?;
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
+    }
+
+    // FX3A
+    pub fn set_pitch(&mut self, x: usize) -> Result<(), Fault> {
+        self.pitch = self.reg[x];
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
     }
 
     // FX55
-    pub fn reg_dump(&mut self, inst: u16) {
-        assert_eq!((inst & 0xf000) >> 12, 0xf);
-        assert_eq!(inst & 0x00ff, 0x55);
-        let x = (inst & 0x0f00) >> 8;
-        for i in 0..x as usize + 1 {
-            let to_write = self.reg[i].to_ne_bytes();
-            self.memory.write(self.addr as usize + i, &to_write);
-        }
-        self.pc += 2;
+    pub fn reg_dump(&mut self, x: usize) -> Result<(), Fault> {
+        let to_write = self.reg[..=x].to_vec();
+        self.write_memory(self.addr as usize, &to_write)?;
+        self.pc = self.pc.wrapping_add(2);
 
         if self.quirks_config.memory {
-            self.addr += x+1;
+            self.addr = self.addr.wrapping_add(x as u16 + 1);
         }
+        Ok(())
     }
 
     // FX65
-    pub fn reg_load(&mut self, inst: u16) {
-        assert_eq!((inst & 0xf000) >> 12, 0xf);
-        assert_eq!(inst & 0x00ff, 0x65);
-        let x = (inst & 0x0f00) >> 8;
-        for i in 0..x as usize + 1 {
-            let readed = self.memory.read(self.addr as usize + i, 1);
-            let value = readed[0];
-            self.reg[i] = value;
-        }
-        self.pc += 2;
+    pub fn reg_load(&mut self, x: usize) -> Result<(), Fault> {
+        let readed = self.read_memory(self.addr as usize, x + 1)?;
+        self.reg[..=x].copy_from_slice(&readed);
+        self.pc = self.pc.wrapping_add(2);
 
         if self.quirks_config.memory {
-            self.addr += x+1;
+            self.addr = self.addr.wrapping_add(x as u16 + 1);
         }
+        Ok(())
+    }
+
+    // FX75
+    pub fn save_rpl_flags(&mut self, x: usize) -> Result<(), Fault> {
+        self.rpl_flags[..=x].copy_from_slice(&self.reg[..=x]);
+        self.rpl_flags_changed = true;
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
+    }
+
+    // FX85
+    pub fn load_rpl_flags(&mut self, x: usize) -> Result<(), Fault> {
+        self.reg[..=x].copy_from_slice(&self.rpl_flags[..=x]);
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
     }
 }
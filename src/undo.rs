@@ -0,0 +1,184 @@
+use std::collections::VecDeque;
+use crate::audio::PATTERN_SIZE;
+use crate::error::ExecutionError;
+use crate::rng::Rng;
+use crate::screen::{Screen, ScreenUndo};
+use crate::stack::Stack;
+use crate::state::{Chip8State, KeypressWait};
+
+/// Instructions the debugger can step back over
+pub const DEFAULT_UNDO_LOG_SIZE: usize = 100_000;
+
+/// How to undo a single instruction: the registers and the rest of the
+/// small state as they were before it, and what it overwrote in the
+/// memory and on the screen
+#[derive(Clone)]
+struct UndoEntry {
+    pc: u16,
+    reg: [u8; 16],
+    addr: u16,
+    stack: Stack,
+    delay_timer: u8,
+    sound_timer: u8,
+    audio_pattern: [u8; PATTERN_SIZE],
+    pitch: u8,
+    rng: Rng,
+    rpl_flags: [u8; 16],
+    keypress_wait: KeypressWait,
+    waiting_for_vblank: bool,
+    exited: bool,
+    cycles: u64,
+    frames: u64,
+    frame_cycle: u32,
+    error: Option<ExecutionError>,
+    // Address and old value of every written byte, in the order of
+    // the writes
+    memory: Vec<(usize, u8)>,
+    screen: Option<ScreenUndo>,
+}
+
+/// Log of the side effects of the last executed instructions, newest
+/// last. Recorded by emulate_instruction while the log is enabled
+#[derive(Clone)]
+pub struct UndoLog {
+    capacity: usize,
+    entries: VecDeque<UndoEntry>,
+    // Memory writes of the instruction being executed
+    pending_writes: Vec<(usize, u8)>,
+}
+
+impl UndoLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::new(),
+            pending_writes: Vec::new(),
+        }
+    }
+
+    /// Instructions that can be undone
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Forget everything, e.g. after the whole state was replaced
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.pending_writes.clear();
+    }
+
+    pub(crate) fn record_write(&mut self, addr: usize, old: u8) {
+        self.pending_writes.push((addr, old));
+    }
+}
+
+/// The state before an instruction, turned into an UndoEntry once the
+/// instruction is done
+pub(crate) struct UndoRecording {
+    entry: UndoEntry,
+    screen: Screen,
+}
+
+impl Chip8State {
+    pub(crate) fn start_undo_recording(&mut self) -> Option<UndoRecording> {
+        let undo_log = self.undo_log.as_mut()?;
+        undo_log.pending_writes.clear();
+        Some(UndoRecording {
+            entry: UndoEntry {
+                pc: self.pc,
+                reg: self.reg,
+                addr: self.addr,
+                stack: self.stack.clone(),
+                delay_timer: self.delay_timer,
+                sound_timer: self.sound_timer,
+                audio_pattern: self.audio_pattern,
+                pitch: self.pitch,
+                rng: self.rng.clone(),
+                rpl_flags: self.rpl_flags,
+                keypress_wait: self.keypress_wait,
+                waiting_for_vblank: self.waiting_for_vblank,
+                exited: self.exited,
+                cycles: self.cycles,
+                frames: self.frames,
+                frame_cycle: self.frame_cycle,
+                error: self.error,
+                memory: Vec::new(),
+                screen: None,
+            },
+            screen: self.screen.clone(),
+        })
+    }
+
+    pub(crate) fn finish_undo_recording(&mut self, recording: UndoRecording) {
+        let UndoRecording { mut entry, screen } = recording;
+        entry.screen = self.screen.undo_since(&screen);
+        let Some(undo_log) = self.undo_log.as_mut() else {
+            return;
+        };
+        entry.memory = std::mem::take(&mut undo_log.pending_writes);
+        undo_log.entries.push_back(entry);
+        if undo_log.entries.len() > undo_log.capacity {
+            undo_log.entries.pop_front();
+        }
+    }
+
+    /// Undo the last executed instruction. Returns false when there's
+    /// nothing left in the undo log
+    pub fn step_back(&mut self) -> bool {
+        let Some(entry) = self.undo_log.as_mut().and_then(|undo_log| undo_log.entries.pop_back()) else {
+            return false;
+        };
+        self.stop_execution();
+        for &(addr, old) in entry.memory.iter().rev() {
+            self.memory.memory[addr] = old;
+        }
+        if let Some(screen) = &entry.screen {
+            self.screen.undo(screen);
+        }
+        self.pc = entry.pc;
+        self.reg = entry.reg;
+        self.addr = entry.addr;
+        self.stack = entry.stack;
+        self.delay_timer = entry.delay_timer;
+        self.sound_timer = entry.sound_timer;
+        self.audio_pattern = entry.audio_pattern;
+        self.pitch = entry.pitch;
+        self.rng = entry.rng;
+        self.rpl_flags = entry.rpl_flags;
+        self.keypress_wait = entry.keypress_wait;
+        self.waiting_for_vblank = entry.waiting_for_vblank;
+        self.exited = entry.exited;
+        self.cycles = entry.cycles;
+        self.frames = entry.frames;
+        self.frame_cycle = entry.frame_cycle;
+        self.error = entry.error;
+        true
+    }
+
+    /// Undo up to `steps` instructions, returns how many were undone
+    pub fn step_back_n(&mut self, steps: u16) -> u16 {
+        let mut undone = 0;
+        while undone < steps && self.step_back() {
+            undone += 1;
+        }
+        undone
+    }
+
+    /// Step back until the execution is at a breakpoint with a true
+    /// condition again, or the undo log runs out. The hit and ignore
+    /// counts are left alone. Returns how many instructions were undone
+    pub fn reverse_continue(&mut self) -> usize {
+        let mut undone = 0;
+        while self.step_back() {
+            undone += 1;
+            if self.breakpoints.iter().any(|bp| bp.matches(self)) {
+                break;
+            }
+        }
+        undone
+    }
+}
@@ -0,0 +1,90 @@
+use std::fmt;
+
+/// Kind of a memory access done by an instruction
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Access {
+    // FX65, DXYN sprites, F002 audio patterns
+    Read,
+    // FX55, FX33
+    Write,
+    // Fetching the instruction itself
+    Execute,
+}
+
+/// Stops the execution when an instruction accesses memory in a range
+#[derive(Clone, Debug)]
+pub struct Watchpoint {
+    // The watched addresses, both ends included
+    pub start: u16,
+    pub end: u16,
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+    pub enabled: bool,
+    // Instructions that did a watched access
+    pub hit_count: u32,
+}
+
+impl Watchpoint {
+    pub fn new(start: u16, end: u16, accesses: &[Access]) -> Self {
+        Self {
+            start: start.min(end),
+            end: start.max(end),
+            read: accesses.contains(&Access::Read),
+            write: accesses.contains(&Access::Write),
+            execute: accesses.contains(&Access::Execute),
+            enabled: true,
+            hit_count: 0,
+        }
+    }
+
+    pub fn watches(&self, access: Access, addr: usize) -> bool {
+        let watched = match access {
+            Access::Read => self.read,
+            Access::Write => self.write,
+            Access::Execute => self.execute,
+        };
+        self.enabled && watched && (self.start as usize..=self.end as usize).contains(&addr)
+    }
+
+    pub fn describe(&self) -> String {
+        let flag = |set: bool, c: char| if set { c } else { '-' };
+        let mut description = format!(
+            "{:#06x}-{:#06x} {}{}{} hits: {}",
+            self.start, self.end,
+            flag(self.read, 'r'), flag(self.write, 'w'), flag(self.execute, 'x'),
+            self.hit_count
+        );
+        if !self.enabled {
+            description += " (disabled)";
+        }
+        description
+    }
+}
+
+/// The first watched access of an instruction, which stopped the
+/// execution
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct WatchHit {
+    pub access: Access,
+    pub addr: u16,
+    // The byte read, written or fetched
+    pub value: u8,
+    // Address and disassembly of the instruction that did the access
+    pub pc: u16,
+    pub instruction: String,
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (verb, preposition) = match self.access {
+            Access::Read => ("read", "from"),
+            Access::Write => ("wrote", "to"),
+            Access::Execute => ("fetched", "from"),
+        };
+        write!(
+            f, "watchpoint: {} at {:#06x} {} {:#04x} {} {:#06x}",
+            self.instruction, self.pc, verb, self.value, preposition, self.addr
+        )
+    }
+}
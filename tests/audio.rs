@@ -0,0 +1,42 @@
+//! Loads an XO-CHIP audio pattern and pitch with F002 and FX3A, and
+//! checks the rate at which the synthesizer plays the pattern.
+
+mod common;
+
+use yayachip8rsemu::audio::{playback_rate, Synthesizer, SAMPLE_RATE};
+use common::chip8_for;
+
+const ROM: &[u8] = &[
+    0xa2, 0x0a, // I = the pattern
+    0xf0, 0x02, // load the pattern
+    0x60, 0x70, // V0 = 112
+    0xf0, 0x3a, // pitch = V0
+    0x12, 0x08, // loop forever
+    // Half of the pattern on, half of it off
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+/// Samples until the first half of the pattern is played
+fn first_half(pattern: &[u8; 16], pitch: u8) -> usize {
+    let mut samples = [0; SAMPLE_RATE as usize / 10];
+    Synthesizer::new().render(pattern, pitch, &mut samples);
+    samples.iter().take_while(|&&sample| sample > 0).count()
+}
+
+#[test]
+fn pattern_and_pitch() {
+    let mut chip8_state = chip8_for("audio.ch8", ROM, &["-q", "xo-chip"]);
+    for _ in 0..4 {
+        chip8_state.emulate_instruction().unwrap();
+    }
+    assert_eq!(chip8_state.audio_pattern[..], ROM[10..]);
+    assert_eq!(chip8_state.pitch, 112);
+
+    // 48 steps of the pitch are an octave above the default of 64
+    assert_eq!(playback_rate(64), 4000.0);
+    assert_eq!(playback_rate(112), 8000.0);
+    let pattern = &chip8_state.audio_pattern;
+    assert_eq!(first_half(pattern, 64), 706);
+    assert_eq!(first_half(pattern, 112), 353);
+}
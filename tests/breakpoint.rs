@@ -0,0 +1,151 @@
+//! Sets conditional breakpoints on a small counting loop, checks where
+//! the execution stops with the hit and ignore counts, and that the
+//! breakpoints are back after creating the machine again. A breakpoint
+//! on FX0A has to let the execution continue past the wait for a key.
+
+mod common;
+
+use std::collections::BTreeMap;
+use std::fs;
+use yayachip8rsemu::args::Chip8Quirks;
+use yayachip8rsemu::breakpoint::{parse_location, Breakpoint, Condition};
+use yayachip8rsemu::state::Chip8State;
+use yayachip8rsemu::undo::{UndoLog, DEFAULT_UNDO_LOG_SIZE};
+use yayachip8rsemu::watchpoint::{Access, Watchpoint};
+use common::{args_for, temp_path};
+
+// V0 = 0, then V0 += 1 forever
+const ROM: [u8; 6] = [0x60, 0x00, 0x70, 0x01, 0x12, 0x02];
+
+const WAIT_FOR_KEY: [u8; 8] = [
+    0x00, 0xe0, // clear the screen
+    0xf0, 0x0a, // V0 = the key pressed and released
+    0x61, 0x01, // V1 = 1
+    0x12, 0x06, // loop forever
+];
+
+const CONFIG: &str = "
+[labels]
+loop = \"0x202\"
+";
+
+#[test]
+fn conditions_parse_and_evaluate() {
+    let labels = BTreeMap::from([("loop".to_owned(), 0x202)]);
+    assert_eq!(parse_location("0x2a4", &labels), Ok(0x2a4));
+    assert_eq!(parse_location("676", &labels), Ok(676));
+    assert_eq!(parse_location("loop", &labels), Ok(0x202));
+    assert!(parse_location("nowhere", &labels).is_err());
+
+    for invalid in ["", "V3 ==", "VG == 1", "(V0", "[I", "V0 = 1", "nowhere == 1", "V0 $ 1"] {
+        assert!(Condition::parse(invalid, &labels).is_err(), "{}", invalid);
+    }
+
+    let mut chip8_state = Chip8State::new(Chip8Quirks::Chip8.quirks_config());
+    chip8_state.reg[3] = 0x10;
+    chip8_state.addr = 0x300;
+    chip8_state.memory.memory[0x300] = 7;
+    chip8_state.memory.memory[0x301] = 0;
+    chip8_state.pc = 0x202;
+    let cases = [
+        ("V3 == 0x10 && [I] != 0", true),
+        ("v3 == 16 && [I + 1] != 0", false),
+        ("[I] == 7 || VF", true),
+        ("!(PC == loop)", false),
+        ("V3 + 1 > 0x10 & 0xff", true),
+        ("V3 ^ 0x10 | DT | ST", false),
+        ("I - 0x300 <= 0 && 1 < 2", true),
+    ];
+    for (text, expected) in cases {
+        let condition = Condition::parse(text, &labels).unwrap();
+        assert_eq!(condition.is_true(&chip8_state), expected, "{}", text);
+    }
+}
+
+#[test]
+fn breakpoints_count_hits_and_persist() {
+    let dir = temp_path("breakpoints");
+    fs::create_dir_all(&dir).unwrap();
+    let rom = dir.join("count.ch8");
+    fs::write(&rom, ROM).unwrap();
+    fs::write(dir.join("count.ch8.toml"), CONFIG).unwrap();
+    let args = args_for(&rom, &["--debug-mode"]);
+
+    let mut chip8_state = args.create_chip8().unwrap();
+    assert!(chip8_state.breakpoints.is_empty());
+    let mut breakpoint = Breakpoint::new(parse_location("loop", &args.labels).unwrap());
+    breakpoint.condition = Some(Condition::parse("V0 >= 5", &args.labels).unwrap());
+    breakpoint.ignore_count = 1;
+    chip8_state.breakpoints.push(breakpoint);
+    // Disabled breakpoints never stop the execution
+    chip8_state.add_breakpoint(0x204);
+    chip8_state.breakpoints[1].enabled = false;
+
+    for _ in 0..100 {
+        if !chip8_state.is_running() {
+            break;
+        }
+        chip8_state.emulate_instruction().unwrap();
+    }
+    assert!(!chip8_state.is_running());
+    assert_eq!(chip8_state.pc, 0x202);
+    assert_eq!(chip8_state.reg[0], 6);
+    assert_eq!(chip8_state.breakpoints[0].hit_count, 2);
+    assert_eq!(chip8_state.breakpoints[1].hit_count, 0);
+
+    args.save_breakpoints(&chip8_state).unwrap();
+    let restarted = args.create_chip8().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(restarted.breakpoints.len(), 2);
+    let loaded = &restarted.breakpoints[0];
+    assert_eq!(loaded.addr, 0x202);
+    assert_eq!(loaded.condition.as_ref().map(|condition| condition.text()), Some("V0 >= 5"));
+    assert_eq!((loaded.ignore_count, loaded.hit_count, loaded.enabled), (1, 0, true));
+    assert!(!restarted.breakpoints[1].enabled);
+}
+
+#[test]
+fn breakpoint_on_a_key_wait_is_hit_once() {
+    let mut chip8_state = Chip8State::new(Chip8Quirks::Chip8.quirks_config());
+    chip8_state.memory.memory[0x200..0x208].copy_from_slice(&WAIT_FOR_KEY);
+    chip8_state.pc = 0x200;
+    chip8_state.undo_log = Some(UndoLog::new(DEFAULT_UNDO_LOG_SIZE));
+    chip8_state.add_breakpoint(0x202);
+    chip8_state.watchpoints.push(Watchpoint::new(0x202, 0x202, &[Access::Execute]));
+
+    chip8_state.emulate_instruction().unwrap();
+    assert!(!chip8_state.is_running());
+    assert_eq!(chip8_state.pc, 0x202);
+
+    // The first fetch stops at the watchpoint, after that waiting for
+    // the key runs FX0A again and again without stopping
+    chip8_state.continue_execution();
+    chip8_state.emulate_instruction().unwrap();
+    assert!(!chip8_state.is_running());
+    assert!(chip8_state.watch_hit.is_some());
+    chip8_state.continue_execution();
+    for _ in 0..50 {
+        chip8_state.emulate_instruction().unwrap();
+    }
+    assert!(chip8_state.is_running());
+    assert_eq!(chip8_state.pc, 0x202);
+    chip8_state.key_pressed[5] = true;
+    chip8_state.emulate_instruction().unwrap();
+    chip8_state.key_pressed[5] = false;
+    for _ in 0..2 {
+        chip8_state.emulate_instruction().unwrap();
+    }
+    assert!(chip8_state.is_running());
+    assert_eq!((chip8_state.pc, chip8_state.reg[0], chip8_state.reg[1]), (0x206, 5, 1));
+    assert_eq!(chip8_state.breakpoints[0].hit_count, 1);
+    assert_eq!(chip8_state.watchpoints[0].hit_count, 1);
+
+    // The whole wait is a single step back
+    assert!(chip8_state.step_back());
+    assert_eq!(chip8_state.pc, 0x204);
+    assert!(chip8_state.step_back());
+    assert_eq!((chip8_state.pc, chip8_state.reg[0]), (0x202, 0));
+    assert!(chip8_state.step_back());
+    assert_eq!(chip8_state.pc, 0x200);
+    assert!(!chip8_state.step_back());
+}
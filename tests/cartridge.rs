@@ -0,0 +1,50 @@
+//! Builds an Octo cartridge GIF and loads it like a ROM, with a stand-in
+//! for the Octo compiler.
+
+mod common;
+
+use std::fs;
+use yayachip8rsemu::cartridge::Cartridge;
+use yayachip8rsemu::state::Platform;
+use common::{args_for, fake_octo_cli, temp_path, write_cartridge, FAKE_OCTO_CLI_ROM};
+
+#[test]
+#[cfg(unix)]
+fn cartridge_loads_with_its_options() {
+    let cartridge_path = temp_path("cartridge.gif");
+    // Long enough to need a second frame
+    let source = format!(": main\n{}  loop again\n", "  clear\n".repeat(10));
+    write_cartridge(&cartridge_path, &format!(r##"{{
+        "program": {:?},
+        "options": {{
+            "tickrate": 20,
+            "maxSize": 3583,
+            "backgroundColor": "#000000",
+            "fillColor": "#FFCC00",
+            "fillColor2": "#FF6600",
+            "blendColor": "#662200",
+            "shiftQuirks": false,
+            "loadStoreQuirks": false,
+            "clipQuirks": true,
+            "vBlankQuirks": false,
+            "jumpQuirks": false,
+            "logicQuirks": false
+        }}
+    }}"##, source));
+
+    let cartridge = Cartridge::decode(&fs::read(&cartridge_path).unwrap()).unwrap();
+    assert_eq!(cartridge.program, source);
+
+    let compiler = fake_octo_cli("octo-cli");
+    let args = args_for(&cartridge_path, &["--octo-cli", &compiler.to_string_lossy()]);
+    let chip8_state = args.create_chip8().unwrap();
+    fs::remove_file(&cartridge_path).unwrap();
+    fs::remove_file(&compiler).unwrap();
+
+    assert_eq!(args.instructions_per_frame, 20);
+    assert_eq!(args.palette, [0x000000, 0xffcc00, 0xff6600, 0x662200]);
+    assert_eq!(args.quirks_config.platform, Platform::SuperChip);
+    assert!(args.quirks_config.memory);
+    assert!(!args.quirks_config.shifting);
+    assert_eq!(chip8_state.memory.memory[0x200..0x202], FAKE_OCTO_CLI_ROM);
+}
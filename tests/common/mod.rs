@@ -0,0 +1,102 @@
+//! Helpers shared by the integration tests. Every test file is its own
+//! crate and uses only some of them.
+#![allow(dead_code)]
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use clap::Parser;
+use yayachip8rsemu::args::{Args, Cli};
+use yayachip8rsemu::state::Chip8State;
+
+#[derive(Parser)]
+struct TestCli {
+    #[command(flatten)]
+    cli: Cli,
+}
+
+/// Path of a directory or file under tests/
+pub fn test_dir(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join(name)
+}
+
+/// Path of a ROM in tests/roms
+pub fn rom_path(name: &str) -> PathBuf {
+    test_dir("roms").join(name)
+}
+
+/// Path in the temp directory that no other test process uses
+pub fn temp_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("yayachip8rsemu-{}-{}", std::process::id(), name))
+}
+
+/// Parse the options like the binaries do, with the ROM given. The RPL
+/// flags files go to the temp directory instead of next to the ROM.
+/// Errors of both the parsing and the merging with the config files
+/// come back as text
+pub fn try_args_for(rom: &Path, options: &[&str]) -> Result<Args, String> {
+    let rom = rom.to_string_lossy();
+    let rpl_dir = env::temp_dir().to_string_lossy().into_owned();
+    let command_line = ["test", "-f", &rom, "--rpl-dir", &rpl_dir].into_iter()
+        .chain(options.iter().copied());
+    TestCli::try_parse_from(command_line)
+        .map_err(|e| e.to_string())?
+        .cli.to_args()
+        .map_err(|e| e.to_string())
+}
+
+pub fn args_for(rom: &Path, options: &[&str]) -> Args {
+    try_args_for(rom, options).unwrap()
+}
+
+/// Write the ROM to the temp directory, returns its path
+pub fn write_rom(name: &str, rom: &[u8]) -> PathBuf {
+    let path = temp_path(name);
+    fs::write(&path, rom).unwrap();
+    path
+}
+
+/// Create the machine like the binaries do for a ROM that only exists
+/// as bytes. The ROM file is gone again afterwards
+pub fn chip8_for(name: &str, rom: &[u8], options: &[&str]) -> Chip8State {
+    let path = write_rom(name, rom);
+    let chip8_state = args_for(&path, options).create_chip8().unwrap();
+    fs::remove_file(&path).unwrap();
+    chip8_state
+}
+
+/// Side of the frames of the cartridges written by write_cartridge
+const CARTRIDGE_SIZE: u16 = 16;
+
+/// Write an Octo cartridge carrying the JSON. The payload goes in the
+/// low two bits of the pixels, spread over as many frames as needed
+pub fn write_cartridge(path: &Path, json: &str) {
+    let mut payload = (json.len() as u32).to_be_bytes().to_vec();
+    payload.extend(json.bytes());
+    let mut pixels: Vec<u8> = payload.iter()
+        .flat_map(|byte| [6, 4, 2, 0].map(|shift| (byte >> shift) & 3))
+        .collect();
+    let frame_size = CARTRIDGE_SIZE as usize * CARTRIDGE_SIZE as usize;
+    pixels.resize(pixels.len().div_ceil(frame_size) * frame_size, 0);
+
+    let palette: Vec<u8> = (0..16).flat_map(|i| [i * 16, i * 16, i * 16]).collect();
+    let mut file = fs::File::create(path).unwrap();
+    let mut encoder = gif::Encoder::new(&mut file, CARTRIDGE_SIZE, CARTRIDGE_SIZE, &palette).unwrap();
+    for frame in pixels.chunks(frame_size) {
+        let frame = gif::Frame::from_indexed_pixels(CARTRIDGE_SIZE, CARTRIDGE_SIZE, frame, None);
+        encoder.write_frame(&frame).unwrap();
+    }
+}
+
+/// What the stand-in for the Octo compiler outputs for any source: 00E0
+pub const FAKE_OCTO_CLI_ROM: [u8; 2] = [0x00, 0xe0];
+
+/// Write a stand-in for the Octo compiler, to be given with --octo-cli
+#[cfg(unix)]
+pub fn fake_octo_cli(name: &str) -> PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+    let path = temp_path(name);
+    fs::write(&path, "#!/bin/sh\nprintf '\\000\\340' > \"$2\"\n").unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    path
+}
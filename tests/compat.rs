@@ -0,0 +1,76 @@
+//! Runs every ROM in tests/roms under every quirks preset and compares
+//! the final screen with the golden snapshot in tests/snapshots.
+//!
+//! Any ROM can be dropped into tests/roms, e.g. the ones from the
+//! Timendus test suite. Run with UPDATE_SNAPSHOTS=1 to write the
+//! snapshots of new ROMs or to accept changed ones.
+
+mod common;
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use clap::ValueEnum;
+use yayachip8rsemu::args::{Args, Chip8Quirks};
+use yayachip8rsemu::rng::DEFAULT_SEED;
+use common::test_dir;
+
+const FRAMES: u64 = 120;
+
+/// The defaults of the command line, with the preset and a fixed seed
+fn args_for(rom: &Path, quirks: Chip8Quirks) -> Args {
+    let preset = quirks.to_possible_value().unwrap().get_name().to_owned();
+    common::args_for(rom, &["-q", &preset, "--seed", &DEFAULT_SEED.to_string()])
+}
+
+/// Run the ROM and describe how it ended: the screen, followed by
+/// the error if the execution halted
+fn run(rom: &Path, quirks: Chip8Quirks) -> String {
+    let args = args_for(rom, quirks);
+    let mut chip8_state = args.create_chip8().expect("ROM should load");
+    let mut snapshot = String::new();
+    while chip8_state.frames < FRAMES && chip8_state.is_running() {
+        if let Err(e) = chip8_state.run_frame(args.instructions_per_frame) {
+            snapshot = format!("error: {}\n", e);
+        }
+    }
+    chip8_state.screen.to_ascii() + &snapshot
+}
+
+#[test]
+fn roms_match_snapshots() {
+    let update = env::var_os("UPDATE_SNAPSHOTS").is_some();
+    let mut roms: Vec<PathBuf> = fs::read_dir(test_dir("roms"))
+        .expect("tests/roms should exist")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "ch8"))
+        .collect();
+    roms.sort();
+    assert!(!roms.is_empty(), "no ROMs in tests/roms");
+
+    let mut failures = Vec::new();
+    for rom in roms.iter() {
+        for quirks in Chip8Quirks::value_variants() {
+            let preset = quirks.to_possible_value().unwrap().get_name().to_owned();
+            let name = format!("{}.{}.txt", rom.file_stem().unwrap().to_string_lossy(), preset);
+            let snapshot_path = test_dir("snapshots").join(&name);
+            let actual = run(rom, *quirks);
+            if update {
+                fs::write(&snapshot_path, &actual).unwrap();
+                continue;
+            }
+            match fs::read_to_string(&snapshot_path) {
+                Ok(expected) if expected == actual => {},
+                Ok(expected) => failures.push(format!(
+                    "{} differs\nexpected:\n{}actual:\n{}", name, expected, actual
+                )),
+                Err(_) => failures.push(format!("{} is missing", name)),
+            }
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{}\nrun with UPDATE_SNAPSHOTS=1 to accept the changes",
+        failures.join("\n")
+    );
+}
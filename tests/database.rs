@@ -0,0 +1,52 @@
+//! Looks up a ROM in a small database written in the format of the
+//! CHIP-8 community database.
+
+mod common;
+
+use std::fs;
+use yayachip8rsemu::args::Chip8Quirks;
+use yayachip8rsemu::database::{rom_hash, Database};
+use common::temp_path;
+
+const ROM: &[u8] = &[0x00, 0xe0, 0x12, 0x00];
+
+#[test]
+fn known_rom_gets_its_settings() {
+    let dir = temp_path("database");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("programs.json"), format!(r##"[{{
+        "title": "Test ROM",
+        "roms": {{
+            "{}": {{
+                "platforms": ["megachip8", "superchip"],
+                "quirkyPlatforms": {{ "superchip": {{ "shift": false }} }},
+                "colors": {{ "pixels": ["#102030", "#405060"] }},
+                "keys": {{ "up": 5, "a": 6 }}
+            }}
+        }}
+    }}]"##, rom_hash(ROM))).unwrap();
+    fs::write(dir.join("platforms.json"), r#"[{
+        "id": "superchip",
+        "name": "Modern SUPER-CHIP",
+        "defaultTickrate": 30,
+        "quirks": { "shift": true, "memoryLeaveIUnchanged": true, "wrap": false, "jump": true }
+    }]"#).unwrap();
+
+    let database = Database::load(&dir).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(database.lookup(&[0x12, 0x00]).is_none());
+
+    let found = database.lookup(ROM).expect("ROM should be in the database");
+    assert_eq!(found.describe(), "Test ROM (Modern SUPER-CHIP)");
+    assert_eq!(found.preset, Chip8Quirks::SuperChip);
+    assert_eq!(found.instructions_per_frame, Some(30));
+    // The ROM's own quirks come after the platform's ones
+    assert_eq!(found.quirks.first(), Some(&("shifting".to_owned(), true)));
+    assert_eq!(found.quirks.last(), Some(&("shifting".to_owned(), false)));
+    assert!(found.quirks.contains(&("memory".to_owned(), false)));
+    assert!(found.quirks.contains(&("clipping".to_owned(), true)));
+    let palette = found.palette.unwrap();
+    assert_eq!(&palette[..2], &[0x102030, 0x405060]);
+    assert_eq!(found.key_map.get(&5).map(String::as_str), Some("Up"));
+    assert_eq!(found.key_map.get(&6).map(String::as_str), Some("Space"));
+}
@@ -0,0 +1,34 @@
+//! Decodes opcodes whose meaning depends on the platform and the
+//! quirks, like BNNN, 00FD and F000 NNNN.
+
+use yayachip8rsemu::disasm::Instruction;
+use yayachip8rsemu::state::QuirksConfig;
+
+#[test]
+fn decoding_depends_on_the_platform() {
+    let chip8 = QuirksConfig::get_chip8();
+    let super_chip = QuirksConfig::get_super_chip();
+    let xo_chip = QuirksConfig::get_xo_chip();
+
+    // The jumping quirk turns BNNN into BXNN
+    assert_eq!(Instruction::decode(&[0xb1, 0x23], &chip8), Instruction::JmpPlus { nnn: 0x123 });
+    assert_eq!(Instruction::decode(&[0xb1, 0x23], &super_chip), Instruction::JmpPlusX { x: 1, nnn: 0x123 });
+
+    // SUPER-CHIP instructions are machine code routines on CHIP-8
+    assert_eq!(Instruction::decode(&[0x00, 0xfd], &chip8), Instruction::CallRca1802CodeRoutine { nnn: 0x0fd });
+    assert_eq!(Instruction::decode(&[0x00, 0xfd], &super_chip), Instruction::Exit);
+    assert_eq!(Instruction::decode(&[0x00, 0xd1], &super_chip), Instruction::CallRca1802CodeRoutine { nnn: 0x0d1 });
+    assert_eq!(Instruction::decode(&[0x00, 0xd1], &xo_chip), Instruction::ScrollUp { n: 1 });
+    assert_eq!(Instruction::decode(&[0xf1, 0x75], &chip8), Instruction::Unknown { opcode: 0xf175 });
+    assert_eq!(Instruction::decode(&[0xf1, 0x75], &super_chip), Instruction::SaveRplFlags { x: 1 });
+
+    // Only XO-CHIP has the long load, which is four bytes
+    let long_load = [0xf0, 0x00, 0x12, 0x34];
+    assert_eq!(Instruction::decode(&long_load, &chip8), Instruction::Unknown { opcode: 0xf000 });
+    assert_eq!(Instruction::size_from_opcode(0xf000, &chip8), 2);
+    let decoded = Instruction::decode(&long_load, &xo_chip);
+    assert_eq!(decoded, Instruction::LongSetAddr { nnnn: 0x1234 });
+    assert_eq!((decoded.size(), Instruction::size_from_opcode(0xf000, &xo_chip)), (4, 4));
+    assert_eq!(Instruction::decode(&[0x51, 0x32], &super_chip), Instruction::Unknown { opcode: 0x5132 });
+    assert_eq!(Instruction::decode(&[0x51, 0x32], &xo_chip), Instruction::SaveRegsRange { x: 1, y: 3 });
+}
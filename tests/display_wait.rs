@@ -0,0 +1,28 @@
+//! Draws in a loop with the display_wait quirk on and off, and checks
+//! that DXYN ends the frame when it's on.
+
+mod common;
+
+use common::chip8_for;
+
+const ROM: &[u8] = &[
+    0xd0, 0x01, // draw
+    0x70, 0x01, // V0 += 1
+    0x12, 0x00, // again
+];
+
+#[test]
+fn draw_waits_for_the_next_frame() {
+    let mut chip8_state = chip8_for("wait.ch8", ROM, &["-q", "chip8"]);
+    assert!(chip8_state.quirks_config.display_wait);
+    chip8_state.run_frame(16).unwrap();
+    assert_eq!((chip8_state.frames, chip8_state.pc, chip8_state.reg[0]), (1, 0x202, 0));
+    chip8_state.run_frame(16).unwrap();
+    assert_eq!((chip8_state.frames, chip8_state.pc, chip8_state.reg[0]), (2, 0x202, 1));
+    // The frame still lasts for all of its cycles
+    assert_eq!(chip8_state.cycles, 32);
+
+    let mut chip8_state = chip8_for("no-wait.ch8", ROM, &["-q", "chip8", "--quirk", "display_wait=off"]);
+    chip8_state.run_frame(16).unwrap();
+    assert_eq!((chip8_state.frames, chip8_state.reg[0]), (1, 5));
+}
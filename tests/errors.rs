@@ -0,0 +1,117 @@
+//! Runs small ROMs that fault under every error policy and checks that
+//! Halt reports the fault, Nop skips the instruction and Wrap wraps
+//! the stack and the addresses around.
+
+mod common;
+
+use yayachip8rsemu::args::Chip8Quirks;
+use yayachip8rsemu::error::ExecutionError;
+use yayachip8rsemu::state::Chip8State;
+use common::chip8_for;
+
+const UNKNOWN_OPCODE: &[u8] = &[
+    0xe0, 0x00, // not an instruction
+    0x61, 0x05, // V1 = 5
+    0x12, 0x04, // loop forever
+];
+
+const STACK_UNDERFLOW: &[u8] = &[
+    0x00, 0xee, // return without a call
+    0x61, 0x05, // V1 = 5
+    0x12, 0x04, // loop forever
+];
+
+// Calls itself until the stack is full
+const STACK_OVERFLOW: &[u8] = &[0x22, 0x00];
+
+/// Run the ROM for a while, returns the state and the error that
+/// halted the execution, if any
+fn run(name: &str, rom: &[u8], options: &[&str]) -> (Chip8State, Option<ExecutionError>) {
+    let mut chip8_state = chip8_for(name, rom, options);
+    for _ in 0..100 {
+        if let Err(e) = chip8_state.emulate_instruction() {
+            return (chip8_state, Some(e));
+        }
+    }
+    (chip8_state, None)
+}
+
+#[test]
+fn unknown_opcodes() {
+    let (chip8_state, error) = run("unknown-halt.ch8", UNKNOWN_OPCODE, &["--on-error", "halt"]);
+    assert_eq!(error, Some(ExecutionError::UnknownOpcode { pc: 0x200, opcode: 0xe000 }));
+    assert_eq!(chip8_state.pc, 0x200);
+    assert!(!chip8_state.is_running());
+
+    for policy in ["nop", "wrap"] {
+        let (chip8_state, error) = run("unknown-skip.ch8", UNKNOWN_OPCODE, &["--on-error", policy]);
+        assert_eq!(error, None, "{}", policy);
+        assert_eq!(chip8_state.reg[1], 5, "{}", policy);
+    }
+}
+
+#[test]
+fn stack_underflow() {
+    let (_, error) = run("underflow-halt.ch8", STACK_UNDERFLOW, &["--on-error", "halt"]);
+    assert_eq!(error, Some(ExecutionError::StackUnderflow { pc: 0x200, opcode: 0x00ee }));
+
+    let (chip8_state, error) = run("underflow-nop.ch8", STACK_UNDERFLOW, &["--on-error", "nop"]);
+    assert_eq!(error, None);
+    assert_eq!(chip8_state.reg[1], 5);
+
+    // The empty stack gives back whatever is at the other end of it
+    let (_, error) = run("underflow-wrap.ch8", STACK_UNDERFLOW, &["--on-error", "wrap"]);
+    assert_eq!(error, None);
+}
+
+#[test]
+fn stack_overflow() {
+    let (chip8_state, error) = run("overflow-halt.ch8", STACK_OVERFLOW, &["--on-error", "halt"]);
+    assert_eq!(error, Some(ExecutionError::StackOverflow { pc: 0x200, opcode: 0x2200 }));
+    assert_eq!(chip8_state.cycles, 16);
+
+    // The call that doesn't fit is skipped
+    let (chip8_state, error) = run("overflow-nop.ch8", STACK_OVERFLOW, &["--on-error", "nop"]);
+    assert_eq!(error, None);
+    assert_ne!(chip8_state.pc, 0x200);
+
+    let (chip8_state, error) = run("overflow-wrap.ch8", STACK_OVERFLOW, &["--on-error", "wrap"]);
+    assert_eq!(error, None);
+    assert_eq!(chip8_state.pc, 0x200);
+}
+
+#[test]
+fn program_counter_at_the_end_of_memory() {
+    // Jump to the last instruction of the 4KiB memory, V0 = 1
+    let mut rom = vec![0; 0xe00];
+    rom[..2].copy_from_slice(&[0x1f, 0xfe]);
+    rom[0xdfe..].copy_from_slice(&[0x60, 0x01]);
+
+    let (chip8_state, error) = run("end-halt.ch8", &rom, &["--on-error", "halt"]);
+    assert_eq!(error, Some(ExecutionError::MemoryOutOfBounds { pc: 0x1000, opcode: 0, addr: 0x1000 }));
+    assert_eq!(chip8_state.reg[0], 1);
+
+    // Wrapping around goes back to the start of the memory
+    let (mut chip8_state, _) = run("end-wrap.ch8", &rom[..2], &["--on-error", "wrap"]);
+    chip8_state.memory.memory[0xffe..].copy_from_slice(&[0x60, 0x01]);
+    chip8_state.pc = 0xffe;
+    chip8_state.emulate_instruction().unwrap();
+    assert_eq!(chip8_state.pc, 0);
+
+    // On XO-CHIP the last instruction is at the end of the 16 bit
+    // address space, the program counter has to wrap instead of
+    // overflowing
+    let mut chip8_state = Chip8State::new(Chip8Quirks::XoChip.quirks_config());
+    chip8_state.memory.memory[0xfffe..].copy_from_slice(&[0x60, 0x01]);
+    chip8_state.pc = 0xfffe;
+    chip8_state.emulate_instruction().unwrap();
+    assert_eq!((chip8_state.pc, chip8_state.reg[0]), (0, 1));
+
+    chip8_state.memory.memory[0xfffe..].copy_from_slice(&[0x2f, 0x00]);
+    chip8_state.memory.memory[0xf00..0xf02].copy_from_slice(&[0x00, 0xee]);
+    chip8_state.pc = 0xfffe;
+    chip8_state.emulate_instruction().unwrap();
+    assert_eq!(chip8_state.pc, 0xf00);
+    chip8_state.emulate_instruction().unwrap();
+    assert_eq!(chip8_state.pc, 0);
+}
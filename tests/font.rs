@@ -0,0 +1,27 @@
+//! Points I at digits with FX29 and FX30 on a machine created without
+//! the command line, which has to come with the default font.
+
+use yayachip8rsemu::args::Chip8Quirks;
+use yayachip8rsemu::font::Font;
+use yayachip8rsemu::state::Chip8State;
+
+const ROM: [u8; 6] = [
+    0x60, 0x09, // V0 = 9
+    0xf0, 0x29, // I = digit V0
+    0xf0, 0x30, // I = big digit V0
+];
+
+#[test]
+fn new_machines_have_the_default_font() {
+    let mut chip8_state = Chip8State::new(Chip8Quirks::SuperChip.quirks_config());
+    chip8_state.memory.memory[0x200..0x206].copy_from_slice(&ROM);
+    chip8_state.pc = 0x200;
+
+    chip8_state.emulate_instruction().unwrap();
+    chip8_state.emulate_instruction().unwrap();
+    let addr = chip8_state.addr as usize;
+    assert_eq!(chip8_state.memory.memory[addr..addr + 5], Font::Vip.small_glyphs()[45..50]);
+    chip8_state.emulate_instruction().unwrap();
+    let addr = chip8_state.addr as usize;
+    assert_eq!(chip8_state.memory.memory[addr..addr + 10], Font::Vip.big_glyphs()[90..100]);
+}
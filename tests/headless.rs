@@ -0,0 +1,128 @@
+//! Runs the headless binary like a build machine would and checks the
+//! screen dumps, the register state and the exit status, including for
+//! malformed command lines, and what it keeps for the next run.
+
+mod common;
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+use common::{args_for, rom_path, temp_path, test_dir, write_rom};
+
+// V0 = 0, then V0 += 1 forever
+const COUNTER: &[u8] = &[0x60, 0x00, 0x70, 0x01, 0x12, 0x02];
+
+// V0 = the key pressed and released, then loop forever
+const WAIT_FOR_KEY: &[u8] = &[0xf0, 0x0a, 0x12, 0x02];
+
+// Save V0 = 0x2a in the RPL user flags, then loop forever
+const SAVE_FLAGS: &[u8] = &[0x60, 0x2a, 0xf0, 0x75, 0x12, 0x04];
+
+fn headless(rom: &Path, options: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_headless"))
+        .arg("-f").arg(rom)
+        .arg("--rpl-dir").arg(env::temp_dir())
+        .args(options)
+        .output()
+        .expect("the headless binary should run")
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn ascii_dump_matches_the_snapshot() {
+    // The same run as in tests/compat.rs
+    let output = headless(&rom_path("font.ch8"), &["--frames", "120", "-q", "chip8", "--seed", "0"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let snapshot = fs::read_to_string(test_dir("snapshots").join("font.chip8.txt")).unwrap();
+    let stdout = stdout(&output);
+    assert!(stdout.starts_with(&snapshot), "{}", stdout);
+    assert!(stdout[snapshot.len()..].starts_with("pc: "), "{}", stdout);
+}
+
+#[test]
+fn pbm_and_png_dumps() {
+    let output = headless(&rom_path("font.ch8"), &["--frames", "10", "--format", "pbm"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    let mut lines = stdout.lines();
+    assert_eq!(lines.next(), Some("P1"));
+    assert_eq!(lines.next(), Some("64 32"));
+    assert!(lines.take(32).all(|line| line.split(' ').count() == 64));
+
+    let output = headless(&rom_path("font.ch8"), &["--frames", "10", "--format", "png"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("--output"), "{}", stderr(&output));
+
+    let png_path = temp_path("screen.png");
+    let output = headless(&rom_path("font.ch8"), &[
+        "--frames", "10", "--format", "png", "--output", &png_path.to_string_lossy(),
+    ]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let decoder = png::Decoder::new(fs::File::open(&png_path).unwrap());
+    let reader = decoder.read_info().unwrap();
+    assert_eq!((reader.info().width, reader.info().height), (64, 32));
+    fs::remove_file(&png_path).unwrap();
+}
+
+#[test]
+fn until_pc() {
+    let rom = write_rom("until.ch8", COUNTER);
+    let reached = headless(&rom, &["--until-pc", "0x204"]);
+    let missed = headless(&rom, &["--until-pc", "0x300", "--frames", "5"]);
+    fs::remove_file(&rom).unwrap();
+
+    assert!(reached.status.success(), "{}", stderr(&reached));
+    assert!(stdout(&reached).contains("pc: 516\n"), "{}", stdout(&reached));
+    assert!(stdout(&reached).contains("V0:   1 0x01"), "{}", stdout(&reached));
+
+    assert!(!missed.status.success());
+    assert!(stderr(&missed).contains("0x300 wasn't reached in 5 frames"), "{}", stderr(&missed));
+}
+
+#[test]
+fn scripted_key_presses() {
+    let rom = write_rom("keys.ch8", WAIT_FOR_KEY);
+    let pressed = headless(&rom, &["--frames", "10", "--key", "2:a:3"]);
+    let malformed: Vec<(&str, Output)> = ["2", "1:2:3:4", "x:1", "1:g", "1:10", "1:1:z"].into_iter()
+        .map(|key| (key, headless(&rom, &["--frames", "10", "--key", key])))
+        .collect();
+    fs::remove_file(&rom).unwrap();
+
+    assert!(pressed.status.success(), "{}", stderr(&pressed));
+    assert!(stdout(&pressed).contains("V0:  10 0x0a"), "{}", stdout(&pressed));
+
+    for (key, output) in malformed {
+        assert!(!output.status.success(), "--key {} was accepted", key);
+        assert!(stderr(&output).contains("--key"), "{}", stderr(&output));
+    }
+}
+
+#[test]
+fn rpl_flags_are_saved_and_breakpoints_need_a_flag() {
+    let rom = write_rom("persist.ch8", SAVE_FLAGS);
+    let args = args_for(&rom, &["--debug-mode"]);
+    let mut chip8_state = args.create_chip8().unwrap();
+    chip8_state.add_breakpoint(0x204);
+    args.save_breakpoints(&chip8_state).unwrap();
+
+    let ignored = headless(&rom, &["-q", "super-chip", "--debug-mode", "--frames", "1"]);
+    let flags = fs::read(args.rpl_flags_path()).unwrap();
+    let stopped = headless(&rom, &["-q", "super-chip", "--debug-mode", "--frames", "1", "--breakpoints"]);
+    fs::remove_file(&rom).unwrap();
+    fs::remove_file(args.rpl_flags_path()).unwrap();
+    fs::remove_file(args.breakpoints_path()).unwrap();
+
+    assert!(ignored.status.success(), "{}", stderr(&ignored));
+    assert!(stdout(&ignored).contains("cycles: 16 "), "{}", stdout(&ignored));
+    assert_eq!(flags[0], 0x2a);
+    assert!(stdout(&stopped).contains("pc: 516\n"), "{}", stdout(&stopped));
+    assert!(stdout(&stopped).contains("cycles: 2 "), "{}", stdout(&stopped));
+}
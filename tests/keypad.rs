@@ -0,0 +1,34 @@
+//! Presses and releases keys during FX0A and checks that it blocks
+//! until the key is released, like on the COSMAC VIP.
+
+mod common;
+
+use common::chip8_for;
+
+const ROM: &[u8] = &[
+    0xf3, 0x0a, // V3 = the key pressed and released
+    0x12, 0x02, // loop forever
+];
+
+#[test]
+fn key_is_stored_after_the_release() {
+    let mut chip8_state = chip8_for("keypad.ch8", ROM, &[]);
+    for _ in 0..3 {
+        chip8_state.emulate_instruction().unwrap();
+    }
+    assert_eq!(chip8_state.pc, 0x200);
+
+    chip8_state.key_pressed[7] = true;
+    for _ in 0..3 {
+        chip8_state.emulate_instruction().unwrap();
+    }
+    // Held down, but not released yet
+    assert_eq!((chip8_state.pc, chip8_state.reg[3]), (0x200, 0));
+
+    // Other keys don't matter once one was pressed
+    chip8_state.key_pressed[2] = true;
+    chip8_state.emulate_instruction().unwrap();
+    chip8_state.key_pressed[7] = false;
+    chip8_state.emulate_instruction().unwrap();
+    assert_eq!((chip8_state.pc, chip8_state.reg[3]), (0x202, 7));
+}
@@ -0,0 +1,112 @@
+//! Records a movie while pressing keys, plays it back and checks that
+//! the run is reproduced exactly, also when breakpoints stop frames in
+//! the middle, and that a changed movie is caught as a desync.
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+use yayachip8rsemu::args::Args;
+use yayachip8rsemu::movie::{MoviePlayer, MovieRecorder};
+use yayachip8rsemu::state::Chip8State;
+use common::{args_for, rom_path, temp_path};
+
+const FRAMES: u64 = 200;
+
+fn args_with_seed(seed: &str) -> Args {
+    args_for(&rom_path("quirks.ch8"), &["-q", "xo-chip", "--seed", seed])
+}
+
+fn describe(chip8_state: &Chip8State) -> String {
+    chip8_state.screen.to_ascii() + &chip8_state.get_state_string()
+}
+
+/// Play the movie from a fresh start, returns the final state or the
+/// desync
+fn play(args: &Args, path: &Path) -> Result<Chip8State, String> {
+    let mut chip8_state = args.create_chip8().unwrap();
+    let mut player = MoviePlayer::load(path, &args.rom_hash).unwrap();
+    player.prepare(&mut chip8_state).unwrap();
+    assert_eq!(player.len(), FRAMES);
+    while !player.is_finished() {
+        chip8_state.poll_input(&mut player);
+        chip8_state.run_frame(player.instructions_per_frame()).unwrap();
+        player.check_frame(&chip8_state)?;
+    }
+    Ok(chip8_state)
+}
+
+/// Record a movie that presses keys now and then, returns the final
+/// state
+fn record(args: &Args, path: &Path) -> Chip8State {
+    let mut chip8_state = args.create_chip8().unwrap();
+    let mut recorder = MovieRecorder::new(&chip8_state, &args.rom_hash);
+    for frame in 0..FRAMES {
+        chip8_state.key_pressed = [false; 16];
+        chip8_state.key_pressed[(frame / 20 % 16) as usize] = frame % 3 == 0;
+        chip8_state.run_frame(args.instructions_per_frame).unwrap();
+        recorder.record_frame(&chip8_state);
+    }
+    recorder.save(&chip8_state, path).unwrap();
+    chip8_state
+}
+
+#[test]
+fn movie_plays_back_exactly() {
+    let args = args_with_seed("7");
+    let path = temp_path("exact.movie");
+    let chip8_state = record(&args, &path);
+
+    // The seed given on the command line doesn't matter, the movie
+    // brings its own
+    let played = play(&args_with_seed("8"), &path).unwrap();
+    assert_eq!(describe(&played), describe(&chip8_state));
+
+    // A different start has to be noticed
+    let json = fs::read_to_string(&path).unwrap();
+    fs::write(&path, json.replacen("\"cycles\":0", "\"cycles\":1", 1)).unwrap();
+    let desync = play(&args, &path).err().expect("the changed movie should desync");
+    fs::remove_file(&path).unwrap();
+    assert!(desync.contains("desynced"), "{}", desync);
+}
+
+#[test]
+fn frames_stopped_in_the_middle_play_back_exactly() {
+    let args = args_with_seed("7");
+    let path = temp_path("stopped.movie");
+    let recorded = record(&args, &path);
+
+    let mut chip8_state = args.create_chip8().unwrap();
+    let mut player = MoviePlayer::load(&path, &args.rom_hash).unwrap();
+    fs::remove_file(&path).unwrap();
+    player.prepare(&mut chip8_state).unwrap();
+    // Somewhere in the middle of the first frame
+    for _ in 0..5 {
+        chip8_state.emulate_instruction().unwrap();
+    }
+    let breakpoint = chip8_state.pc;
+    player.prepare(&mut chip8_state).unwrap();
+    chip8_state.add_breakpoint(breakpoint);
+
+    // Like the emulator: the keys are read at the start of a frame, and
+    // only frames that ran to their end are checked
+    let mut stopped_in_the_middle = 0;
+    while !player.is_finished() {
+        if chip8_state.frame_cycle == 0 {
+            chip8_state.poll_input(&mut player);
+        }
+        let frame = chip8_state.frames;
+        chip8_state.run_frame(player.instructions_per_frame()).unwrap();
+        if !chip8_state.is_running() {
+            if chip8_state.frame_cycle != 0 {
+                stopped_in_the_middle += 1;
+            }
+            chip8_state.continue_execution();
+        }
+        if chip8_state.frames != frame {
+            player.check_frame(&chip8_state).unwrap();
+        }
+    }
+    assert!(stopped_in_the_middle > 0);
+    assert_eq!(describe(&chip8_state), describe(&recorded));
+}
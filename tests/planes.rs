@@ -0,0 +1,43 @@
+//! Draws on both XO-CHIP planes and checks that FN01 limits clearing,
+//! drawing and scrolling to the selected planes.
+
+mod common;
+
+use yayachip8rsemu::state::Chip8State;
+use common::chip8_for;
+
+const ROM: &[u8] = &[
+    0xf3, 0x01, // select both planes
+    0xa2, 0x10, // I = the sprite
+    0xd0, 0x01, // draw one row at 0, 0
+    0xf2, 0x01, // select the second plane
+    0x00, 0xc1, // scroll down by 1
+    0xf1, 0x01, // select the first plane
+    0x00, 0xe0, // clear the screen
+    0x12, 0x0e, // loop forever
+    0xc0,       // sprite for the first plane
+    0x60,       // sprite for the second plane
+];
+
+/// The first three pixels of the first two rows
+fn corner(chip8_state: &Chip8State) -> [[u8; 3]; 2] {
+    std::array::from_fn(|y| std::array::from_fn(|x| chip8_state.screen.get(x, y)))
+}
+
+#[test]
+fn instructions_act_on_the_selected_planes() {
+    let mut chip8_state = chip8_for("planes.ch8", ROM, &["-q", "xo-chip"]);
+    for _ in 0..3 {
+        chip8_state.emulate_instruction().unwrap();
+    }
+    // Each plane got its own sprite row
+    assert_eq!(corner(&chip8_state), [[1, 3, 2], [0, 0, 0]]);
+
+    chip8_state.emulate_instruction().unwrap();
+    chip8_state.emulate_instruction().unwrap();
+    assert_eq!(corner(&chip8_state), [[1, 1, 0], [0, 2, 2]]);
+
+    chip8_state.emulate_instruction().unwrap();
+    chip8_state.emulate_instruction().unwrap();
+    assert_eq!(corner(&chip8_state), [[0, 0, 0], [0, 2, 2]]);
+}
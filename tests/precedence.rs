@@ -0,0 +1,186 @@
+//! Gives conflicting settings from the command line, the ROM config
+//! file, an Octo cartridge and the ROM database, and checks that each
+//! source wins over the ones after it.
+
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+use yayachip8rsemu::args::{Args, Chip8Quirks};
+use yayachip8rsemu::database::rom_hash;
+use yayachip8rsemu::state::{Platform, DEFAULT_INSTRUCTIONS_PER_FRAME};
+use common::{args_for, try_args_for, temp_path, FAKE_OCTO_CLI_ROM};
+
+const CONFIG: &str = r#"
+instructions_per_frame = 40
+palette = ["000000", "ffffff", "ff0000", "00ff00"]
+
+[quirk]
+jumping = false
+
+[keys]
+5 = "W"
+"#;
+
+/// A directory with the ROM, a config file and a database that knows
+/// the ROM. Its contents are what the stand-in Octo compiler outputs,
+/// so cartridges compiled by it are in the database too
+struct Sources {
+    dir: PathBuf,
+}
+
+impl Sources {
+    fn new(name: &str) -> Self {
+        let dir = temp_path(name);
+        fs::create_dir_all(dir.join("database")).unwrap();
+        fs::write(dir.join("rom.ch8"), FAKE_OCTO_CLI_ROM).unwrap();
+        fs::write(dir.join("rom.toml"), CONFIG).unwrap();
+        fs::write(dir.join("database").join("programs.json"), format!(r##"[{{
+            "title": "Test ROM",
+            "roms": {{
+                "{}": {{
+                    "platforms": ["superchip"],
+                    "quirkyPlatforms": {{ "superchip": {{ "shift": false }} }},
+                    "colors": {{ "pixels": ["#111111", "#222222"] }},
+                    "keys": {{ "up": 5 }}
+                }}
+            }}
+        }}]"##, rom_hash(&FAKE_OCTO_CLI_ROM))).unwrap();
+        fs::write(dir.join("database").join("platforms.json"), r#"[{
+            "id": "superchip",
+            "name": "Modern SUPER-CHIP",
+            "defaultTickrate": 30,
+            "quirks": { "shift": true, "memoryLeaveIUnchanged": true, "wrap": false, "jump": true }
+        }]"#).unwrap();
+        Self { dir }
+    }
+
+    fn path(&self, name: &str) -> String {
+        self.dir.join(name).to_string_lossy().into_owned()
+    }
+
+    fn args(&self, rom: &str, options: &[&str]) -> Args {
+        args_for(&self.dir.join(rom), options)
+    }
+}
+
+impl Drop for Sources {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[test]
+fn command_line_over_config_over_database() {
+    let sources = Sources::new("precedence-binary");
+    let database = sources.path("database");
+    let config = sources.path("rom.toml");
+
+    let args = sources.args("rom.ch8", &["--database", &database]);
+    assert_eq!(args.database_entry.as_deref(), Some("Test ROM (Modern SUPER-CHIP)"));
+    assert_eq!(args.quirks_config.platform, Platform::SuperChip);
+    // The ROM's quirks go on top of its platform's ones
+    assert!(!args.quirks_config.shifting);
+    assert!(args.quirks_config.jumping);
+    assert_eq!(args.instructions_per_frame, 30);
+    assert_eq!(args.palette[..2], [0x111111, 0x222222]);
+    assert_eq!(args.key_map.get(&5).map(String::as_str), Some("Up"));
+
+    let args = sources.args("rom.ch8", &["--database", &database, "--config", &config]);
+    // The preset still comes from the database, with the config file's
+    // quirks over the database's ones
+    assert_eq!(args.quirks_config.platform, Platform::SuperChip);
+    assert!(!args.quirks_config.shifting);
+    assert!(!args.quirks_config.jumping);
+    assert_eq!(args.instructions_per_frame, 40);
+    assert_eq!(args.palette, [0x000000, 0xffffff, 0xff0000, 0x00ff00]);
+    assert_eq!(args.key_map.get(&5).map(String::as_str), Some("W"));
+
+    // A preset from the config file replaces the database's quirks
+    fs::write(&config, format!("quirks = \"xo-chip\"\n{}", CONFIG)).unwrap();
+    let args = sources.args("rom.ch8", &["--database", &database, "--config", &config]);
+    assert_eq!(args.quirks_config.platform, Platform::XoChip);
+    assert_eq!(args.quirks_config.shifting, Chip8Quirks::XoChip.quirks_config().shifting);
+    assert!(!args.quirks_config.jumping);
+
+    let args = sources.args("rom.ch8", &[
+        "--database", &database, "--config", &config,
+        "-q", "chip8", "--quirk", "jumping=on", "--instructions-per-frame", "50",
+        "--palette", "010101,020202,030303,040404",
+    ]);
+    assert_eq!(args.quirks_config.platform, Platform::Chip8);
+    assert!(args.quirks_config.jumping);
+    assert_eq!(args.instructions_per_frame, 50);
+    assert_eq!(args.palette, [0x010101, 0x020202, 0x030303, 0x040404]);
+    // Nothing on the command line sets keys
+    assert_eq!(args.key_map.get(&5).map(String::as_str), Some("W"));
+
+    // Without any source the defaults are used
+    let args = sources.args("rom.ch8", &[]);
+    assert_eq!(args.quirks_config.platform, Platform::Chip8);
+    assert_eq!(args.instructions_per_frame, DEFAULT_INSTRUCTIONS_PER_FRAME);
+    assert!(args.key_map.is_empty());
+}
+
+#[test]
+#[cfg(unix)]
+fn cartridge_over_database() {
+    let sources = Sources::new("precedence-cartridge");
+    let database = sources.path("database");
+    let config = sources.path("rom.toml");
+    let octo_cli_path = common::fake_octo_cli("precedence-octo-cli");
+    let octo_cli = octo_cli_path.to_string_lossy();
+    common::write_cartridge(&sources.dir.join("rom.gif"), r##"{
+        "program": ": main\n  clear\n",
+        "options": {
+            "tickrate": 20,
+            "maxSize": 65024,
+            "backgroundColor": "#000001",
+            "fillColor": "#000002",
+            "fillColor2": "#000003",
+            "blendColor": "#000004",
+            "shiftQuirks": true,
+            "loadStoreQuirks": false,
+            "clipQuirks": false,
+            "vBlankQuirks": false,
+            "jumpQuirks": false,
+            "logicQuirks": false
+        }
+    }"##);
+
+    let args = sources.args("rom.gif", &["--octo-cli", &octo_cli, "--database", &database]);
+    assert_eq!(args.rom, FAKE_OCTO_CLI_ROM);
+    assert_eq!(args.database_entry.as_deref(), Some("Test ROM (Modern SUPER-CHIP)"));
+    assert_eq!(args.quirks_config.platform, Platform::XoChip);
+    assert!(args.quirks_config.shifting);
+    assert!(!args.quirks_config.jumping);
+    assert_eq!(args.instructions_per_frame, 20);
+    assert_eq!(args.palette, [0x000001, 0x000002, 0x000003, 0x000004]);
+    // The cartridge has no keys, so the database's are used
+    assert_eq!(args.key_map.get(&5).map(String::as_str), Some("Up"));
+
+    let args = sources.args("rom.gif", &[
+        "--octo-cli", &octo_cli, "--database", &database, "--config", &config,
+    ]);
+    fs::remove_file(&octo_cli_path).unwrap();
+    assert_eq!(args.quirks_config.platform, Platform::XoChip);
+    assert_eq!(args.instructions_per_frame, 40);
+    assert_eq!(args.palette, [0x000000, 0xffffff, 0xff0000, 0x00ff00]);
+}
+
+#[test]
+fn unknown_quirks_are_rejected() {
+    let sources = Sources::new("precedence-unknown");
+    let rom = sources.dir.join("rom.ch8");
+
+    let error = try_args_for(&rom, &["--quirk", "bogus=on"]).err().unwrap();
+    assert!(error.contains("unknown quirk bogus"), "{}", error);
+
+    let error = try_args_for(&rom, &["--quirk", "shifting=maybe"]).err().unwrap();
+    assert!(error.contains("shifting=maybe"), "{}", error);
+
+    let config = sources.path("bogus.toml");
+    fs::write(&config, "[quirk]\nbogus = true\n").unwrap();
+    let error = try_args_for(&rom, &["--config", &config]).err().unwrap();
+    assert!(error.contains("unknown quirk bogus"), "{}", error);
+}
@@ -0,0 +1,44 @@
+//! Records a run in the rewind buffer, then plays it backwards and
+//! checks that every frame comes back exactly as it was.
+
+mod common;
+
+use yayachip8rsemu::rewind::Rewind;
+use common::{args_for, rom_path};
+
+const FRAMES: usize = 300;
+
+#[test]
+fn rewinding_restores_every_frame() {
+    let args = args_for(&rom_path("quirks.ch8"), &["-q", "xo-chip", "--seed", "1"]);
+    let mut chip8_state = args.create_chip8().unwrap();
+
+    let mut rewind = Rewind::new(FRAMES);
+    let mut recorded = Vec::new();
+    rewind.push(&chip8_state);
+    recorded.push(chip8_state.screen.to_ascii() + &chip8_state.get_state_string());
+    for _ in 0..FRAMES {
+        chip8_state.run_frame(args.instructions_per_frame).unwrap();
+        rewind.push(&chip8_state);
+        recorded.push(chip8_state.screen.to_ascii() + &chip8_state.get_state_string());
+    }
+    assert_eq!(rewind.len(), FRAMES);
+    // A full XO-CHIP machine is more than 64KiB, the history of all the
+    // frames should take much less than a copy per frame
+    assert!(rewind.size() < 200 * 1024, "history takes {} bytes", rewind.size());
+
+    recorded.pop();
+    while let Some(expected) = recorded.pop() {
+        assert!(rewind.rewind(&mut chip8_state));
+        assert_eq!(chip8_state.screen.to_ascii() + &chip8_state.get_state_string(), expected);
+    }
+    assert!(!rewind.rewind(&mut chip8_state));
+
+    // The history is bounded
+    let mut rewind = Rewind::new(10);
+    for _ in 0..20 {
+        chip8_state.run_frame(args.instructions_per_frame).unwrap();
+        rewind.push(&chip8_state);
+    }
+    assert_eq!(rewind.len(), 10);
+}
@@ -0,0 +1,65 @@
+//! Runs a ROM that keeps drawing random numbers with CXNN and checks
+//! that the numbers depend only on the seed, that a cloned state
+//! continues the same sequence, and how the VIP routine picks them.
+
+mod common;
+
+use yayachip8rsemu::state::Chip8State;
+use common::chip8_for;
+
+const ROM: &[u8] = &[
+    0xc0, 0xff, // V0 = random
+    0x12, 0x00, // again
+];
+
+const NUMBERS: usize = 64;
+
+fn start(name: &str, options: &[&str]) -> Chip8State {
+    chip8_for(name, ROM, options)
+}
+
+fn random_numbers(chip8_state: &mut Chip8State, count: usize) -> Vec<u8> {
+    (0..count).map(|_| {
+        chip8_state.emulate_instruction().unwrap();
+        chip8_state.emulate_instruction().unwrap();
+        chip8_state.reg[0]
+    }).collect()
+}
+
+#[test]
+fn seed_decides_the_numbers() {
+    let numbers = random_numbers(&mut start("seed-a.ch8", &["--seed", "42"]), NUMBERS);
+    assert_eq!(random_numbers(&mut start("seed-b.ch8", &["--seed", "42"]), NUMBERS), numbers);
+    assert_ne!(random_numbers(&mut start("seed-c.ch8", &["--seed", "43"]), NUMBERS), numbers);
+    // Not a constant either
+    assert!(numbers.iter().any(|&number| number != numbers[0]));
+}
+
+#[test]
+fn cloned_state_continues_the_sequence() {
+    for rng in ["xorshift", "vip"] {
+        let mut chip8_state = start("clone.ch8", &["--seed", "7", "--rng", rng]);
+        random_numbers(&mut chip8_state, NUMBERS);
+        let mut snapshot = chip8_state.clone();
+        assert_eq!(
+            random_numbers(&mut snapshot, NUMBERS),
+            random_numbers(&mut chip8_state, NUMBERS),
+            "{}", rng
+        );
+    }
+}
+
+#[test]
+fn vip_routine_reads_the_interpreter_page() {
+    let mut chip8_state = start("vip.ch8", &["--seed", "0", "--rng", "vip"]);
+    chip8_state.memory.memory[0x100..0x200].fill(0);
+    // With nothing in the page only the counter gets added: 0 + 1
+    // rotated right is 0x80, then 0x80 + 2 rotated right is 0x41
+    assert_eq!(random_numbers(&mut chip8_state, 2), [0x80, 0x41]);
+
+    let mut quiet = start("vip-quiet.ch8", &["--seed", "0", "--rng", "vip"]);
+    quiet.memory.memory[0x100..0x200].fill(0);
+    let mut noisy = start("vip-noisy.ch8", &["--seed", "0", "--rng", "vip"]);
+    noisy.memory.memory[0x100..0x200].fill(0x5a);
+    assert_ne!(random_numbers(&mut quiet, NUMBERS), random_numbers(&mut noisy, NUMBERS));
+}
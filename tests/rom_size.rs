@@ -0,0 +1,35 @@
+//! Loads ROMs at different offsets and checks that the ones that don't
+//! fit in the platform's memory are rejected instead of panicking.
+
+mod common;
+
+use std::fs;
+use yayachip8rsemu::args::Chip8Quirks;
+use yayachip8rsemu::state::Chip8State;
+use common::{args_for, write_rom};
+
+#[test]
+fn roms_have_to_fit_after_the_offset() {
+    let rom = write_rom("big.ch8", &[0x12; 0xe01]);
+    let too_big = args_for(&rom, &["-q", "chip8"]).create_chip8().err();
+    let fits = args_for(&rom, &["-q", "chip8", "--offset", "0x100"]).create_chip8();
+    let xo_chip = args_for(&rom, &["-q", "xo-chip"]).create_chip8();
+    fs::remove_file(&rom).unwrap();
+
+    let error = too_big.expect("the ROM shouldn't fit").to_string();
+    assert_eq!(error, "ROM is 3585 bytes long, but only 3584 bytes are available when loading at 0x200 on Chip8");
+    let chip8_state = fits.unwrap();
+    assert_eq!(chip8_state.memory.memory[0x100..0xf01], [0x12; 0xe01]);
+    assert_eq!(chip8_state.memory.memory[0xf01], 0);
+    assert!(xo_chip.is_ok());
+}
+
+#[test]
+fn memory_images_have_to_fit() {
+    let chip8 = Chip8Quirks::Chip8.quirks_config();
+    let chip8_state = Chip8State::from_memory(chip8.clone(), vec![0xaa; 0x1000]).unwrap();
+    assert_eq!(chip8_state.memory.memory, [0xaa; 0x1000]);
+
+    let error = Chip8State::from_memory(chip8, vec![0xaa; 0x1001]).err().expect("the image shouldn't fit");
+    assert!(error.to_string().starts_with("ROM is 4097 bytes long"), "{}", error);
+}
@@ -0,0 +1,82 @@
+//! Saves the state in the middle of a run, then checks that continuing
+//! from the saved state ends up exactly where the uninterrupted run
+//! does, and that states of other ROMs or versions and broken states
+//! are rejected.
+
+mod common;
+
+use std::fs;
+use yayachip8rsemu::args::Args;
+use yayachip8rsemu::state::Chip8State;
+use common::{rom_path, temp_path};
+
+fn args_for(rom: &str) -> Args {
+    common::args_for(&rom_path(rom), &["-q", "xo-chip", "--seed", "1"])
+}
+
+fn run_until(chip8_state: &mut Chip8State, args: &Args, frames: u64) {
+    while chip8_state.frames < frames && chip8_state.is_running() {
+        chip8_state.run_frame(args.instructions_per_frame).unwrap();
+    }
+}
+
+#[test]
+fn loaded_state_continues_the_run() {
+    let args = args_for("quirks.ch8");
+    let mut uninterrupted = args.create_chip8().unwrap();
+    run_until(&mut uninterrupted, &args, 200);
+
+    let path = temp_path("resume.state");
+    let mut saved = args.create_chip8().unwrap();
+    run_until(&mut saved, &args, 70);
+    args.save_state_file(&saved, &path).unwrap();
+
+    let mut resumed = args.create_chip8().unwrap();
+    args.load_state_file(&mut resumed, &path).unwrap();
+    fs::remove_file(&path).unwrap();
+    run_until(&mut resumed, &args, 200);
+
+    assert_eq!(resumed.screen.to_ascii(), uninterrupted.screen.to_ascii());
+    assert_eq!(resumed.get_state_string(), uninterrupted.get_state_string());
+}
+
+#[test]
+fn other_roms_and_versions_are_rejected() {
+    let args = args_for("quirks.ch8");
+    let path = temp_path("reject.state");
+    args.save_state_file(&args.create_chip8().unwrap(), &path).unwrap();
+
+    let other_args = args_for("font.ch8");
+    let error = other_args.load_state_file(&mut other_args.create_chip8().unwrap(), &path)
+        .unwrap_err();
+    assert!(error.to_string().contains("another ROM"), "{}", error);
+
+    let json = fs::read_to_string(&path).unwrap();
+    fs::write(&path, json.replacen("\"version\":1", "\"version\":999", 1)).unwrap();
+    let error = args.load_state_file(&mut args.create_chip8().unwrap(), &path).unwrap_err();
+    fs::remove_file(&path).unwrap();
+    assert!(error.to_string().contains("version 999"), "{}", error);
+}
+
+#[test]
+fn broken_states_are_rejected() {
+    let args = args_for("quirks.ch8");
+    let path = temp_path("broken.state");
+    args.save_state_file(&args.create_chip8().unwrap(), &path).unwrap();
+    let json = fs::read_to_string(&path).unwrap();
+
+    let broken = [
+        ("\"sp\":0", "\"sp\":40", "stack pointer 40"),
+        ("\"planes\":1", "\"planes\":7", "planes 0b111"),
+        ("\"pixels\":[0", "\"pixels\":[9", "pixel value 9"),
+        ("\"keypress_wait\":\"NotWaiting\"", "\"keypress_wait\":{\"Release\":20}", "key 20"),
+    ];
+    for (field, value, message) in broken {
+        assert!(json.contains(field), "{}", field);
+        fs::write(&path, json.replacen(field, value, 1)).unwrap();
+        let mut chip8_state = args.create_chip8().unwrap();
+        let error = args.load_state_file(&mut chip8_state, &path).unwrap_err();
+        assert!(error.to_string().contains(message), "{}", error);
+    }
+    fs::remove_file(&path).unwrap();
+}
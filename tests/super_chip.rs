@@ -0,0 +1,53 @@
+//! Points I at the big SUPER-CHIP digits with FX30, and checks that the
+//! RPL user flags saved with FX75 are back with FX85 in the next run.
+
+mod common;
+
+use std::fs;
+use yayachip8rsemu::font::Font;
+use common::{args_for, chip8_for, write_rom};
+
+const BIG_DIGIT: &[u8] = &[
+    0x60, 0x07, // V0 = 7
+    0xf0, 0x30, // I = big digit V0
+];
+
+const FLAGS: &[u8] = &[
+    0xf2, 0x85, // load V0-V2 from the flags
+    0x60, 0x01, // V0 = 1
+    0x61, 0x02, // V1 = 2
+    0x62, 0x03, // V2 = 3
+    0xf2, 0x75, // save V0-V2 to the flags
+    0x12, 0x0a, // loop forever
+];
+
+#[test]
+fn big_digits() {
+    let mut chip8_state = chip8_for("big-digit.ch8", BIG_DIGIT, &["-q", "super-chip"]);
+    chip8_state.emulate_instruction().unwrap();
+    chip8_state.emulate_instruction().unwrap();
+    let addr = chip8_state.addr as usize;
+    assert_eq!(addr, chip8_state.big_font_addr as usize + 7 * 10);
+    assert_eq!(chip8_state.memory.memory[addr..addr + 10], Font::Vip.big_glyphs()[70..80]);
+}
+
+#[test]
+fn rpl_flags_persist_across_runs() {
+    let rom = write_rom("flags.ch8", FLAGS);
+    let args = args_for(&rom, &["-q", "super-chip"]);
+
+    let mut chip8_state = args.create_chip8().unwrap();
+    chip8_state.emulate_instruction().unwrap();
+    assert_eq!(chip8_state.reg[..3], [0, 0, 0]);
+    for _ in 0..4 {
+        chip8_state.emulate_instruction().unwrap();
+    }
+    assert!(chip8_state.rpl_flags_changed);
+    args.save_rpl_flags(&chip8_state).unwrap();
+
+    let mut chip8_state = args.create_chip8().unwrap();
+    fs::remove_file(&rom).unwrap();
+    fs::remove_file(args.rpl_flags_path()).unwrap();
+    chip8_state.emulate_instruction().unwrap();
+    assert_eq!(chip8_state.reg[..3], [1, 2, 3]);
+}
@@ -0,0 +1,43 @@
+//! Sets the timers and checks that they tick once per emulated frame,
+//! whether the ROM runs frame by frame or is stepped in the debugger.
+
+mod common;
+
+use common::chip8_for;
+
+const ROM: &[u8] = &[
+    0x60, 0x0a, // V0 = 10
+    0xf0, 0x15, // delay timer = V0
+    0xf0, 0x18, // sound timer = V0
+    0x12, 0x06, // loop forever
+];
+
+#[test]
+fn timers_tick_by_frame() {
+    let mut chip8_state = chip8_for("timers.ch8", ROM, &["--instructions-per-frame", "16"]);
+    for _ in 0..15 {
+        chip8_state.emulate_instruction().unwrap();
+    }
+    assert_eq!((chip8_state.delay_timer, chip8_state.sound_timer), (10, 10));
+    chip8_state.emulate_instruction().unwrap();
+    assert_eq!((chip8_state.delay_timer, chip8_state.sound_timer), (9, 9));
+    assert_eq!((chip8_state.frames, chip8_state.cycles), (1, 16));
+
+    for _ in 0..5 {
+        chip8_state.run_frame(16).unwrap();
+    }
+    assert_eq!((chip8_state.delay_timer, chip8_state.frames), (4, 6));
+
+    // Time stands still while the execution is stopped
+    chip8_state.stop_execution();
+    chip8_state.run_frame(16).unwrap();
+    chip8_state.emulate_instruction().unwrap();
+    assert_eq!((chip8_state.delay_timer, chip8_state.cycles), (4, 96));
+
+    // Stepping a frame's worth of instructions ticks once
+    chip8_state.step(16);
+    while chip8_state.is_running() {
+        chip8_state.emulate_instruction().unwrap();
+    }
+    assert_eq!((chip8_state.delay_timer, chip8_state.frames, chip8_state.cycles), (3, 7, 112));
+}
@@ -0,0 +1,47 @@
+//! Runs a ROM with the undo log of the debugger, then steps back over
+//! every instruction and checks that each one is undone exactly.
+
+mod common;
+
+use yayachip8rsemu::state::Chip8State;
+use common::{args_for, rom_path};
+
+const INSTRUCTIONS: usize = 3000;
+
+fn describe(chip8_state: &Chip8State) -> (String, Vec<u8>) {
+    (
+        chip8_state.screen.to_ascii() + &chip8_state.get_state_string(),
+        chip8_state.memory.memory.clone(),
+    )
+}
+
+#[test]
+fn stepping_back_undoes_every_instruction() {
+    let args = args_for(&rom_path("quirks.ch8"), &["-q", "xo-chip", "--seed", "1", "--debug-mode"]);
+    let mut chip8_state = args.create_chip8().unwrap();
+
+    let mut recorded = Vec::new();
+    for _ in 0..INSTRUCTIONS {
+        recorded.push(describe(&chip8_state));
+        chip8_state.emulate_instruction().unwrap();
+    }
+    let breakpoint = chip8_state.pc;
+
+    while let Some(expected) = recorded.pop() {
+        assert!(chip8_state.step_back());
+        assert!(!chip8_state.is_running());
+        assert_eq!(describe(&chip8_state), expected);
+    }
+    assert!(!chip8_state.step_back());
+
+    // Reverse continue stops at the last time a breakpoint was passed
+    chip8_state.continue_execution();
+    for _ in 0..INSTRUCTIONS {
+        chip8_state.emulate_instruction().unwrap();
+    }
+    chip8_state.add_breakpoint(breakpoint);
+    let undone = chip8_state.reverse_continue();
+    assert!(undone > 0);
+    assert_eq!(chip8_state.pc, breakpoint);
+    assert_eq!(chip8_state.step_back_n(5), 5);
+}
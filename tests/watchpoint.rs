@@ -0,0 +1,60 @@
+//! Watches memory written, read and executed by a small ROM and checks
+//! that the execution stops after each watched access with the right
+//! instruction and value.
+
+mod common;
+
+use yayachip8rsemu::state::Chip8State;
+use yayachip8rsemu::watchpoint::{Access, Watchpoint};
+use common::chip8_for;
+
+const ROM: [u8; 12] = [
+    0xa3, 0x00, // I = 0x300
+    0x63, 0x12, // V3 = 0x12
+    0xf3, 0x55, // store V0-V3 at I
+    0xa3, 0x00, // I = 0x300
+    0xf3, 0x65, // load V0-V3 from I
+    0x12, 0x0a, // loop forever
+];
+
+/// Run until the execution stops, returns the message of the
+/// watchpoint that stopped it
+fn run_to_watchpoint(chip8_state: &mut Chip8State) -> String {
+    chip8_state.continue_execution();
+    for _ in 0..100 {
+        chip8_state.emulate_instruction().unwrap();
+        if !chip8_state.is_running() {
+            break;
+        }
+    }
+    assert!(!chip8_state.is_running());
+    chip8_state.watch_hit.as_ref().expect("a watchpoint should have stopped the execution").to_string()
+}
+
+#[test]
+fn watchpoints_stop_on_accesses() {
+    let mut chip8_state = chip8_for("watchpoints.ch8", &ROM, &[]);
+
+    chip8_state.watchpoints.push(Watchpoint::new(0x303, 0x303, &[Access::Write]));
+    chip8_state.watchpoints.push(Watchpoint::new(0x303, 0x302, &[Access::Read]));
+    chip8_state.watchpoints.push(Watchpoint::new(0x20a, 0x20b, &[Access::Execute]));
+    // Disabled watchpoints never stop the execution
+    chip8_state.watchpoints.push(Watchpoint::new(0x200, 0x20b, &[Access::Execute]));
+    chip8_state.watchpoints[3].enabled = false;
+
+    let message = run_to_watchpoint(&mut chip8_state);
+    assert_eq!(chip8_state.pc, 0x206);
+    assert!(message.contains("at 0x0204 wrote 0x12 to 0x0303"), "{}", message);
+
+    let message = run_to_watchpoint(&mut chip8_state);
+    assert_eq!(chip8_state.pc, 0x20a);
+    assert_eq!(chip8_state.reg[3], 0x12);
+    assert!(message.contains("at 0x0208 read 0x00 from 0x0302"), "{}", message);
+
+    let message = run_to_watchpoint(&mut chip8_state);
+    assert!(message.contains("at 0x020a fetched 0x12 from 0x020a"), "{}", message);
+    run_to_watchpoint(&mut chip8_state);
+
+    let hits: Vec<u32> = chip8_state.watchpoints.iter().map(|watchpoint| watchpoint.hit_count).collect();
+    assert_eq!(hits, [1, 1, 2, 0]);
+}
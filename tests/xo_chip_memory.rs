@@ -0,0 +1,56 @@
+//! Runs XO-CHIP ROMs that use the whole 64 KiB with F000 NNNN, skip
+//! over it, and save and load register ranges with 5XY2 and 5XY3.
+
+mod common;
+
+use common::chip8_for;
+
+const LONG_LOAD: &[u8] = &[
+    0xf0, 0x00, 0x80, 0x00, // I = 0x8000
+    0x30, 0x00,             // skip if V0 == 0
+    0xf0, 0x00, 0x12, 0x34, // I = 0x1234, skipped
+    0x61, 0x05,             // V1 = 5
+];
+
+const REGISTER_RANGES: &[u8] = &[
+    0x61, 0x01, // V1 = 1
+    0x62, 0x02, // V2 = 2
+    0x63, 0x03, // V3 = 3
+    0xa3, 0x00, // I = 0x300
+    0x51, 0x32, // save V1-V3
+    0xa3, 0x10, // I = 0x310
+    0x53, 0x12, // save V3-V1
+    0xa3, 0x00, // I = 0x300
+    0x5a, 0xc3, // load VA-VC
+    0x5e, 0xd3, // load VE-VD
+];
+
+#[test]
+fn long_load_is_skipped_as_a_whole() {
+    let mut chip8_state = chip8_for("long-load.ch8", LONG_LOAD, &["-q", "xo-chip"]);
+    assert_eq!(chip8_state.memory.memory.len(), 0x10000);
+
+    chip8_state.emulate_instruction().unwrap();
+    assert_eq!((chip8_state.pc, chip8_state.addr), (0x204, 0x8000));
+    chip8_state.emulate_instruction().unwrap();
+    assert_eq!(chip8_state.pc, 0x20a);
+    chip8_state.emulate_instruction().unwrap();
+    assert_eq!(chip8_state.addr, 0x8000);
+    assert_eq!(chip8_state.reg[1], 5);
+}
+
+#[test]
+fn register_ranges_go_both_ways() {
+    let mut chip8_state = chip8_for("ranges.ch8", REGISTER_RANGES, &["-q", "xo-chip"]);
+    for _ in 0..REGISTER_RANGES.len() / 2 {
+        chip8_state.emulate_instruction().unwrap();
+    }
+    let memory = &chip8_state.memory.memory;
+    assert_eq!(memory[0x300..0x303], [1, 2, 3]);
+    // With X > Y the registers are stored from VX down to VY
+    assert_eq!(memory[0x310..0x313], [3, 2, 1]);
+    assert_eq!(chip8_state.reg[0xa..=0xc], [1, 2, 3]);
+    assert_eq!((chip8_state.reg[0xe], chip8_state.reg[0xd]), (1, 2));
+    // Unlike FX55 and FX65, I is left alone
+    assert_eq!(chip8_state.addr, 0x300);
+}